@@ -1,21 +1,37 @@
+use chrono::{Datelike, Local, NaiveDate, Weekday};
 use std::{
-    io,
+    env, fs,
+    io::{self, Write},
+    num::NonZeroUsize,
+    path::PathBuf,
+    process,
     time::{Duration, Instant},
 };
 //use std::fmt;
-use clap::{Parser, ValueEnum};
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    error::ErrorKind,
+    parser::ValueSource,
+    CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use ratatui::{
     crossterm::event::{self, KeyCode, KeyEventKind},
-    layout::Rect,
+    layout::{Constraint, Layout, Rect},
     style::{Color, Stylize},
-    text::Text,
+    text::{Line, Text},
     widgets::{Cell, Row, Table},
     DefaultTerminal,
 };
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::sync::mpsc;
 use std::thread;
 
 pub mod sudoku;
 
+use sudoku::sudoku::{Grid, Shape, Technique};
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 enum Difficulty {
     // Easy
@@ -26,63 +42,508 @@ enum Difficulty {
     Hard,
 }
 
+impl Difficulty {
+    // Daily puzzles get harder through the week, easy on Monday and hard on Sunday
+    fn of_weekday(weekday: Weekday) -> Self {
+        match weekday {
+            Weekday::Mon | Weekday::Tue => Difficulty::Easy,
+            Weekday::Wed | Weekday::Thu | Weekday::Fri => Difficulty::Medium,
+            Weekday::Sat | Weekday::Sun => Difficulty::Hard,
+        }
+    }
+
+    // Range of the hardest technique a puzzle of this difficulty needs on a board of given size.
+    // Singles are enough for any 4x4 puzzle, so every difficulty is easy there.
+    fn technique_band(&self, size: u8) -> RangeInclusive<Technique> {
+        match self {
+            _ if size == 4 => Technique::NakedSingle..=Technique::HiddenSingle,
+            Difficulty::Easy => Technique::NakedSingle..=Technique::HiddenSingle,
+            Difficulty::Medium => Technique::PointingPair..=Technique::HiddenTriple,
+            Difficulty::Hard => Technique::XWing..=Technique::Swordfish,
+        }
+    }
+
+    // How a puzzle rated out of band of this difficulty differs from it, e.g. a minimal puzzle
+    // found when time budget is exceeded may be harder or not solved logically at all
+    fn describe_out_of_band(
+        &self,
+        board: &sudoku::sudoku::Grid,
+        rating: &sudoku::sudoku::Rating,
+        band: &RangeInclusive<Technique>,
+    ) -> String {
+        match rating.hardest {
+            // A board with all values is rated as not solved, since no technique is needed
+            None if sudoku::sudoku::is_finished(board) => String::from("has no empty cells"),
+            None => String::from("can't be solved with logical techniques, it needs a search"),
+            Some(technique) if technique < *band.start() => format!(
+                "is easier than {:?}, hardest technique: {}",
+                self,
+                technique.name()
+            ),
+            Some(technique) => format!(
+                "is harder than {:?}, hardest technique: {}",
+                self,
+                technique.name()
+            ),
+        }
+    }
+
+    // Larger cages of killer puzzles have more ways to add up to their sums
+    fn max_cage_size(&self) -> u8 {
+        match self {
+            Difficulty::Easy => 3,
+            Difficulty::Medium => 4,
+            Difficulty::Hard => 5,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Solve puzzles given one per line and print their solutions
+    #[command(
+        after_help = "Exit status is 0 if every puzzle has exactly one solution, \
+        3 if a puzzle has multiple solutions, 4 if a puzzle has no solution \
+        and 5 if input is malformed or can't be read"
+    )]
+    Solve {
+        /// File to read puzzles from, standard input is read if not given
+        file: Option<PathBuf>,
+
+        /// Solver searching the solutions. Auto uses dancing links for killer puzzles and
+        /// puzzles larger than 9x9, depth first search otherwise
+        #[arg(long, value_enum, default_value_t = SolverBackend::Auto)]
+        solver: SolverBackend,
+    },
+    /// Generate puzzles and print them with their solutions and ratings
+    Generate {
+        /// Number of puzzles
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+
+        /// Difficulty
+        #[arg(long, value_enum, default_value_t = Difficulty::Medium)]
+        difficulty: Difficulty,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Line)]
+        format: OutputFormat,
+    },
+    /// Play puzzle of the day, same for everyone on the same date. Daily puzzles are always
+    /// classic 9x9 ones without extra constraints, generated without symmetry, minimal or time
+    /// budget, so only --threads, --hide-elapsed-time, --print-puzzle and --export can be given
+    Daily,
+}
+
+impl Command {
+    // Ids of arguments of Args the subcommand doesn't use. Subcommands can't be named in
+    // conflicts of arguments, so these are rejected in main when given.
+    fn ignored_args(&self) -> &'static [&'static str] {
+        match self {
+            Command::Solve { .. } => &[
+                "seed",
+                "time_budget",
+                "symmetry",
+                "minimal",
+                "size",
+                "variant",
+                "constraint",
+                "jigsaw",
+                "difficulty",
+                "hide_elapsed_time",
+                "puzzle",
+                "file",
+                "print_puzzle",
+                "export",
+                "resume",
+            ],
+            Command::Generate { .. } => &[
+                "difficulty",
+                "hide_elapsed_time",
+                "puzzle",
+                "file",
+                "print_puzzle",
+                "export",
+                "resume",
+            ],
+            // Daily puzzle is always the same classic one, so options choosing another game or
+            // changing how it's generated can't be given with it
+            Command::Daily => &[
+                "seed",
+                "time_budget",
+                "symmetry",
+                "minimal",
+                "size",
+                "variant",
+                "constraint",
+                "jigsaw",
+                "difficulty",
+                "puzzle",
+                "file",
+                "resume",
+            ],
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum SolverBackend {
+    // Whichever suits the board
+    Auto,
+    // Depth first search
+    Dfs,
+    // Exact cover with dancing links
+    Dlx,
+}
+
+impl SolverBackend {
+    fn solver(&self, board: &Grid) -> &'static dyn sudoku::sudoku::Solver {
+        match self {
+            SolverBackend::Auto => sudoku::sudoku::solver_of(board),
+            SolverBackend::Dfs => &sudoku::sudoku::DfsSolver,
+            SolverBackend::Dlx => &sudoku::sudoku::DlxSolver,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum ClueSymmetry {
+    // Clues are placed randomly
+    None,
+    // Rotating 180 degrees
+    Rotational,
+    // Mirroring top to bottom
+    Horizontal,
+    // Mirroring left to right
+    Vertical,
+    // Mirroring over the main diagonal
+    Diagonal,
+    // All rotations and mirrors
+    Dihedral,
+}
+
+impl ClueSymmetry {
+    fn symmetry(&self) -> sudoku::sudoku::Symmetry {
+        match self {
+            ClueSymmetry::None => sudoku::sudoku::Symmetry::None,
+            ClueSymmetry::Rotational => sudoku::sudoku::Symmetry::Rotational,
+            ClueSymmetry::Horizontal => sudoku::sudoku::Symmetry::MirrorHorizontal,
+            ClueSymmetry::Vertical => sudoku::sudoku::Symmetry::MirrorVertical,
+            ClueSymmetry::Diagonal => sudoku::sudoku::Symmetry::Diagonal,
+            ClueSymmetry::Dihedral => sudoku::sudoku::Symmetry::Dihedral,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum GameVariant {
+    // Rows, columns and boxes only
+    Classic,
+    // Cages with sums replace most givens
+    Killer,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum ExtraConstraint {
+    // Both main diagonals have every value once, as in Sudoku-X
+    #[value(alias = "x")]
+    Diagonals,
+}
+
+impl ExtraConstraint {
+    fn constraint(&self) -> sudoku::sudoku::Constraint {
+        match self {
+            ExtraConstraint::Diagonals => sudoku::sudoku::Constraint::Diagonals,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum OutputFormat {
+    // Puzzle, solution, score and hardest technique separated by spaces
+    Line,
+    // Array of objects
+    Json,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Seed of the puzzle generator, the same seed always generates the same puzzle
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+
+    /// Seconds after which generation stops with the closest puzzle found so far, which may be
+    /// easier or harder than requested. 0 for no limit, generation still stops the same way if
+    /// the difficulty isn't reached after a few hundred boards
+    #[arg(long, global = true, value_name = "SECS", default_value_t = 60)]
+    time_budget: u64,
+
+    /// Symmetry of the clues of generated puzzles
+    #[arg(long, global = true, value_enum, default_value_t = ClueSymmetry::None)]
+    symmetry: ClueSymmetry,
+
+    /// Generate minimal puzzles, from which no clue can be removed without losing uniqueness.
+    /// Can't be combined with --symmetry, since clues are removed one by one to reach minimal
+    #[arg(
+        long,
+        global = true,
+        default_value_t = false,
+        conflicts_with = "symmetry"
+    )]
+    minimal: bool,
+
+    /// Number of worker threads, number of available CPUs if not given
+    #[arg(long, global = true)]
+    threads: Option<NonZeroUsize>,
+
+    /// Number of rows, columns and values of generated puzzles. Boxes are 2x2, 2x3, 3x3, 3x4
+    /// and 4x4 respectively. Values of 16x16 puzzles are hex digits 0 to F, values above 9 of
+    /// others are letters from A on
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 9,
+        value_parser = PossibleValuesParser::new(["4", "6", "9", "12", "16"])
+            .map(|size| size.parse::<u8>().unwrap())
+    )]
+    size: u8,
+
+    /// Rules of generated puzzles. Killer puzzles are up to 9x9, symmetry and minimal don't apply
+    /// to them and their difficulty is the size of their cages
+    #[arg(long, global = true, value_enum, default_value_t = GameVariant::Classic)]
+    variant: GameVariant,
+
+    /// Extra rule of generated puzzles, may be given more than once. Diagonals (or x) makes both
+    /// main diagonals have every value once
+    #[arg(long, global = true, value_enum)]
+    constraint: Vec<ExtraConstraint>,
+
+    /// Replace boxes of generated puzzles with random connected regions, as in jigsaw sudoku.
+    /// Jigsaw puzzles are up to 12x12, or 9x9 with constraints
+    #[arg(long, global = true, default_value_t = false)]
+    jigsaw: bool,
+
     /// Difficulty
     #[arg(value_enum, default_value_t = Difficulty::Medium)]
     difficulty: Difficulty,
 
     /// Hide elapsed time
-    #[arg(long, default_value_t = false)]
+    #[arg(long, global = true, default_value_t = false)]
     hide_elapsed_time: bool,
+
+    /// Play given puzzle instead of generating one, as 81 characters with 0 or . for empty cells.
+    /// Other sizes are given as 16, 36, 144 or 256 characters, values as with --size. 16x16
+    /// puzzles have . for empty cells only, since 0 is a value
+    #[arg(long, conflicts_with = "file")]
+    puzzle: Option<String>,
+
+    /// Play puzzle on the first non-empty line of file, in the same format as --puzzle. Files
+    /// written by export can be played, the progress on their second line is ignored
+    #[arg(long)]
+    file: Option<PathBuf>,
+
+    /// Print the puzzle in the same format as --puzzle and exit without starting the game
+    #[arg(long, global = true, default_value_t = false)]
+    print_puzzle: bool,
+
+    /// File to write the puzzle and progress to when exported, printed on exit if not given
+    #[arg(long, global = true)]
+    export: Option<PathBuf>,
+
+    /// Continue the game saved on last quit
+    #[arg(long, default_value_t = false, conflicts_with_all = ["puzzle", "file", "print_puzzle"])]
+    resume: bool,
 }
 
-#[derive(Copy, Clone)]
+impl Args {
+    fn generation_options(
+        &self,
+        difficulty: Difficulty,
+        seed: u64,
+        shape: Shape,
+        variant: GameVariant,
+        constraints: &[ExtraConstraint],
+        jigsaw: bool,
+    ) -> sudoku::sudoku::GenerationOptions {
+        sudoku::sudoku::GenerationOptions {
+            shape,
+            variant: match variant {
+                GameVariant::Classic => sudoku::sudoku::Variant::Classic,
+                GameVariant::Killer => sudoku::sudoku::Variant::Killer {
+                    max_cage_size: difficulty.max_cage_size(),
+                },
+            },
+            constraints: constraints
+                .iter()
+                .enumerate()
+                // Giving a constraint twice is the same as giving it once
+                .filter(|(i, constraint)| !constraints[..*i].contains(constraint))
+                .map(|(_, constraint)| constraint.constraint())
+                .collect(),
+            jigsaw,
+            band: difficulty.technique_band(shape.size),
+            seed,
+            // Zero means no limit
+            time_budget: (self.time_budget > 0).then(|| Duration::from_secs(self.time_budget)),
+            threads: self.thread_count(),
+            symmetry: self.symmetry.symmetry(),
+            minimal: self.minimal,
+        }
+    }
+
+    fn shape(&self) -> Shape {
+        Shape::of_size(self.size).expect("size is one of the supported sizes")
+    }
+
+    // Available parallelism can't be queried in some containers, a single thread is used then
+    fn thread_count(&self) -> usize {
+        self.threads.map_or_else(
+            || thread::available_parallelism().map_or(1, |count| count.get()),
+            |count| count.get(),
+        )
+    }
+}
+
+#[derive(Copy, Clone, Default)]
 struct CellData {
     editable: bool,
     conflict: bool,
     highlight: bool,
+    // Cell is a part of the pending hint
+    hint: bool,
+    // Pencil marks, bit n is set when candidate n is noted
+    notes: u32,
+}
+
+// A single edit of a cell, both value and notes are stored so that
+// value changes, deletions and note edits can be reverted the same way
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Move {
+    cell: (u8, u8),
+    old_value: u8,
+    old_notes: u32,
+    new_value: u8,
+    new_notes: u32,
+}
+
+// Next logical step for the player, filling target cell as (row, col, val)
+struct Hint {
+    cells: Vec<(u8, u8)>,
+    target: (u8, u8, u8),
+    explanation: String,
+}
+
+// Characters of lines drawn around cages and regions, corners are shared
+#[derive(Copy, Clone)]
+struct Border {
+    horizontal: char,
+    vertical: char,
 }
 
+// Cages are outlined with dotted lines, so that regions can be outlined with solid ones
+const CAGE_BORDER: Border = Border {
+    horizontal: '┈',
+    vertical: '┊',
+};
+const REGION_BORDER: Border = Border {
+    horizontal: '─',
+    vertical: '│',
+};
+
 struct Board {
-    rows: [[u8; 9]; 9],
-    cell_data: [[CellData; 9]; 9],
+    rows: Grid,
+    // Solution of the initial board, if it has exactly one
+    solution: Option<Grid>,
+    cell_data: Grid<CellData>,
     current_cell: (u8, u8),
-    difficulty: Difficulty,
+    // None for puzzles which are not generated
+    difficulty: Option<Difficulty>,
+    seed: Option<u64>,
+    // Date of the daily puzzle being played
+    daily: Option<NaiveDate>,
+    notes_mode: bool,
+    undo_stack: Vec<Move>,
+    redo_stack: Vec<Move>,
+    // Width and height of the screen the board is drawn on
+    screen_size: (u16, u16),
 }
 
 impl<'a> Board {
-    fn new(difficulty: Difficulty) -> Self {
+    fn new(difficulty: Option<Difficulty>) -> Self {
         let current_cell = (0u8, 0u8);
-        let rows: [[u8; 9]; 9] = [[2; 9]; 9];
-        let cell_data: [[CellData; 9]; 9] = [([CellData {
-            conflict: false,
-            editable: false,
-            highlight: false,
-        }; 9]); 9];
+        let rows: Grid = Grid::new(Shape::CLASSIC);
+        let cell_data: Grid<CellData> = Grid::new(Shape::CLASSIC);
         Self {
             rows,
+            solution: None,
             cell_data,
             current_cell,
             difficulty,
+            seed: None,
+            daily: None,
+            notes_mode: false,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            screen_size: (0, 0),
+        }
+    }
+
+    // Boards up to 9x9 have cells of 3 lines to draw notes in, larger boards have cells of a
+    // single line so that they fit on screen. So do all boards on screens too small for them,
+    // leaving room for the message and status lines.
+    fn is_compact(&self) -> bool {
+        let size = self.rows.shape().size as u16;
+        let (width, height) = self.screen_size;
+        size > 9 || width < size * 5 || height < size * 3 + 3
+    }
+
+    // Width and height of a cell on screen
+    fn cell_size(&self) -> (u16, u16) {
+        if self.is_compact() {
+            (3, 1)
+        } else {
+            (5, 3)
         }
     }
 
     fn create_table(&self) -> Table<'a> {
-        let mut rows: Vec<Row> = Vec::with_capacity(9);
+        let shape = self.rows.shape();
+        let mut rows: Vec<Row> = Vec::with_capacity(shape.size as usize);
         let finished = sudoku::sudoku::is_finished(&self.rows);
-        for row in 0..9 {
-            let mut cells: Vec<Cell> = Vec::with_capacity(9);
-            for col in 0..9 {
+        let cage_map = self.rows.cage_map();
+        let region_shades = self.region_shades();
+        let is_rect_darker = |row: u8, col: u8| match &region_shades {
+            Some(shades) => shades[self.rows.box_index(row, col) as usize],
+            None => ((row / shape.box_rows) % 2) ^ ((col / shape.box_cols) % 2) == 0,
+        };
+        for row in 0..shape.size {
+            let mut cells: Vec<Cell> = Vec::with_capacity(shape.size as usize);
+            for col in 0..shape.size {
                 let bg_color = {
                     if row == self.current_cell.0 && col == self.current_cell.1 {
                         Color::Indexed(180)
+                    } else if self.cell_data[row as usize][col as usize].hint {
+                        Color::Indexed(109)
                     } else if self.cell_data[row as usize][col as usize].conflict {
                         Color::Indexed(162)
+                    } else if !self.rows.extra_units_of(row, col).is_empty() {
+                        // Cells in units of constraints, e.g. diagonals, are tinted
+                        let is_rect_darker = is_rect_darker(row, col);
+                        let is_line_highlighted =
+                            self.current_cell.0 == row || self.current_cell.1 == col;
+                        match (is_rect_darker, is_line_highlighted) {
+                            (true, false) => Color::Indexed(60),
+                            (true, true) => Color::Indexed(61),
+                            (false, false) => Color::Indexed(103),
+                            (false, true) => Color::Indexed(104),
+                        }
                     } else {
                         let is_cell_darker = (row % 2) ^ (col % 2) == 0;
-                        let is_rect_darker = ((row / 3) % 2) ^ ((col / 3) % 2) == 0;
+                        let is_rect_darker = is_rect_darker(row, col);
                         if is_cell_darker {
                             if is_rect_darker {
                                 if self.current_cell.0 == row || self.current_cell.1 == col {
@@ -129,38 +590,207 @@ impl<'a> Board {
                         Color::Black
                     }
                 };
-                let mut char = String::from(" ");
-                if self.rows[row as usize][col as usize] > 0 {
-                    char = format!("{}", self.rows[row as usize][col as usize]);
-                } else if row as u8 == self.current_cell.0 && col as u8 == self.current_cell.1 {
-                    char = String::from("_");
+                // Cells are 3 lines tall, so that notes can be drawn as a mini grid shaped like a
+                // box. Compact cells only show whether there are notes.
+                let notes = self.cell_data[row as usize][col as usize].notes;
+                let val = self.rows[row as usize][col as usize];
+                let value_line = usize::from(!self.is_compact());
+                let mut lines = vec![String::new(), String::from(" "), String::new()];
+                if self.is_compact() {
+                    lines = vec![String::from(" ")];
+                }
+                if val > 0 {
+                    lines[value_line] = shape.value_char(val).to_string();
+                } else if notes != 0 && self.is_compact() {
+                    lines[value_line] = String::from("·");
+                } else if notes != 0 {
+                    lines = (0..shape.box_rows)
+                        .map(|line| {
+                            (1..=shape.box_cols)
+                                .map(|i| {
+                                    let val = line * shape.box_cols + i;
+                                    if notes & (1 << val) != 0 {
+                                        shape.value_char(val)
+                                    } else {
+                                        ' '
+                                    }
+                                })
+                                .collect::<String>()
+                        })
+                        .collect();
+                } else if row == self.current_cell.0 && col == self.current_cell.1 {
+                    lines[value_line] = String::from("_");
                 }
+                // Cage lines are drawn where there is no region line already
+                if self.rows.regions().is_some() {
+                    let region = self.rows.box_index(row, col);
+                    lines = self.draw_border(
+                        row,
+                        col,
+                        |row, col| self.rows.box_index(row, col) == region,
+                        REGION_BORDER,
+                        lines,
+                    );
+                }
+                if let Some(cage) = cage_map[row as usize][col as usize] {
+                    lines = self.draw_border(
+                        row,
+                        col,
+                        |row, col| cage_map[row as usize][col as usize] == Some(cage),
+                        CAGE_BORDER,
+                        lines,
+                    );
+                    let cage = &self.rows.cages()[cage];
+                    // Sum is drawn over lines and spaces only, notes under it would be hidden. The
+                    // sum of the current cell is shown below the board anyway.
+                    let sum = cage.sum.to_string();
+                    if !self.is_compact()
+                        && cage.cells[0] == (row, col)
+                        && !lines[0].chars().take(sum.len()).any(char::is_alphanumeric)
+                    {
+                        lines[0] =
+                            sum.clone() + &lines[0].chars().skip(sum.len()).collect::<String>();
+                    }
+                }
+                let lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
                 cells.insert(
                     col as usize,
-                    Cell::from(Text::from(char).centered())
+                    Cell::from(Text::from(lines).centered())
                         .bg(bg_color)
                         .fg(fg_color),
                 );
             }
-            rows.insert(row as usize, Row::new(cells));
+            rows.insert(row as usize, Row::new(cells).height(self.cell_size().1));
         }
-        let widths = [3; 9];
+        let widths = vec![self.cell_size().0; shape.size as usize];
         Table::new(rows, widths)
             .column_spacing(0)
             .bg(Color::Indexed(0))
     }
 
+    // Shade of each region of a jigsaw board, regions get the shade fewer of their neighbors
+    // already have, so that neighboring regions are mostly told apart
+    fn region_shades(&self) -> Option<Vec<bool>> {
+        self.rows.regions()?;
+        let size = self.rows.shape().size;
+        let mut neighbors: Vec<Vec<u8>> = vec![Vec::new(); size as usize];
+        for row in 0..size {
+            for col in 0..size {
+                let region = self.rows.box_index(row, col);
+                for (next_row, next_col) in [(row + 1, col), (row, col + 1)] {
+                    if next_row == size || next_col == size {
+                        continue;
+                    }
+                    let next = self.rows.box_index(next_row, next_col);
+                    if next != region && !neighbors[region as usize].contains(&next) {
+                        neighbors[region as usize].push(next);
+                        neighbors[next as usize].push(region);
+                    }
+                }
+            }
+        }
+
+        let mut shades: Vec<Option<bool>> = vec![None; size as usize];
+        for region in 0..size as usize {
+            let darker_count = neighbors[region]
+                .iter()
+                .filter(|next| shades[**next as usize] == Some(true))
+                .count();
+            let lighter_count = neighbors[region]
+                .iter()
+                .filter(|next| shades[**next as usize] == Some(false))
+                .count();
+            shades[region] = Some(darker_count <= lighter_count);
+        }
+        Some(
+            shades
+                .into_iter()
+                .map(|shade| shade == Some(true))
+                .collect(),
+        )
+    }
+
+    // Pads lines of a cell to the full cell and draws border lines on the sides bordering cells
+    // outside of its cage or region, given by is_inside. Compact cells only get the lines on
+    // their left and right sides.
+    // @return lines of the cell
+    fn draw_border(
+        &self,
+        row: u8,
+        col: u8,
+        is_inside: impl Fn(u8, u8) -> bool,
+        border: Border,
+        lines: Vec<String>,
+    ) -> Vec<String> {
+        let (width, height) = self.cell_size();
+        let (width, height) = (width as usize, height as usize);
+        let last = self.rows.shape().size - 1;
+        let borders = |row: u8, col: u8, beyond: bool| beyond || !is_inside(row, col);
+        let left = borders(row, col.saturating_sub(1), col == 0);
+        let right = borders(row, col.saturating_add(1).min(last), col == last);
+        let top = borders(row.saturating_sub(1), col, row == 0) && !self.is_compact();
+        let bottom =
+            borders(row.saturating_add(1).min(last), col, row == last) && !self.is_compact();
+
+        let mut chars: Vec<Vec<char>> = (0..height)
+            .map(|line| {
+                let text = lines.get(line).map_or("", |text| text.as_str());
+                format!("{:^width$}", text).chars().collect()
+            })
+            .collect();
+        // Lines are drawn over spaces only, so that values and notes stay visible
+        let mut draw = |line: usize, pos: usize, c: char| {
+            if [' ', border.horizontal, border.vertical].contains(&chars[line][pos]) {
+                chars[line][pos] = c;
+            }
+        };
+        for pos in 0..width {
+            if top {
+                draw(0, pos, border.horizontal);
+            }
+            if bottom {
+                draw(height - 1, pos, border.horizontal);
+            }
+        }
+        for line in 0..height {
+            if left {
+                draw(line, 0, border.vertical);
+            }
+            if right {
+                draw(line, width - 1, border.vertical);
+            }
+        }
+        for (is_corner, line, pos, c) in [
+            (top && left, 0, 0, '┌'),
+            (top && right, 0, width - 1, '┐'),
+            (bottom && left, height - 1, 0, '└'),
+            (bottom && right, height - 1, width - 1, '┘'),
+        ] {
+            if is_corner {
+                draw(line, pos, c);
+            }
+        }
+
+        chars
+            .into_iter()
+            .map(|line| line.into_iter().collect())
+            .collect()
+    }
+
     fn set_current(&mut self, row: u8, col: u8) {
         self.current_cell = (row, col);
         self.update_cell_data();
     }
 
     fn update_cell_data(&mut self) {
-        for row in 0..9 {
-            for col in 0..9 {
+        let shape = self.rows.shape();
+        for row in 0..shape.size {
+            for col in 0..shape.size {
+                // Values repeated in a cage and cage sums which can't be met are conflicts too
                 self.cell_data[row as usize][col as usize].conflict =
                     !sudoku::sudoku::is_valid(&self.rows, row, col);
                 self.cell_data[row as usize][col as usize].highlight = sudoku::sudoku::are_related(
+                    &self.rows,
                     (self.current_cell.0, self.current_cell.1),
                     (row, col),
                 );
@@ -168,17 +798,255 @@ impl<'a> Board {
         }
     }
 
+    // Changes value and notes of current cell, recording the move in undo history
+    fn edit_cell(&mut self, value: u8, notes: u32) {
+        let (row, col) = self.current_cell;
+        let old_value = self.rows[row as usize][col as usize];
+        let old_notes = self.cell_data[row as usize][col as usize].notes;
+        if old_value == value && old_notes == notes {
+            return;
+        }
+
+        self.undo_stack.push(Move {
+            cell: self.current_cell,
+            old_value,
+            old_notes,
+            new_value: value,
+            new_notes: notes,
+        });
+        self.redo_stack.clear();
+        self.write_cell(self.current_cell, value, notes);
+    }
+
+    fn write_cell(&mut self, cell: (u8, u8), value: u8, notes: u32) {
+        self.rows[cell.0 as usize][cell.1 as usize] = value;
+        self.cell_data[cell.0 as usize][cell.1 as usize].notes = notes;
+        self.set_current(cell.0, cell.1);
+    }
+
     fn set_value(&mut self, val: u8) {
-        self.rows[self.current_cell.0 as usize][self.current_cell.1 as usize] = val;
+        let notes =
+            self.cell_data[self.current_cell.0 as usize][self.current_cell.1 as usize].notes;
+        self.edit_cell(val, notes);
+    }
+
+    fn toggle_note(&mut self, val: u8) {
+        let value = self.rows[self.current_cell.0 as usize][self.current_cell.1 as usize];
+        let notes =
+            self.cell_data[self.current_cell.0 as usize][self.current_cell.1 as usize].notes;
+        self.edit_cell(value, notes ^ (1 << val));
+    }
+
+    fn clear_cell(&mut self) {
+        self.edit_cell(0, 0);
+    }
+
+    // Finds the next value player can fill. Wrong values are reported first, since logical
+    // steps can't be trusted on such a board.
+    fn find_hint(&self) -> Option<Hint> {
+        let size = self.rows.shape().size;
+        if let Some(solution) = &self.solution {
+            for row in 0..size {
+                for col in 0..size {
+                    let val = self.rows[row as usize][col as usize];
+                    let expected = solution[row as usize][col as usize];
+                    if val != 0 && val != expected {
+                        return Some(Hint {
+                            cells: vec![(row, col)],
+                            target: (row, col, expected),
+                            explanation: format!(
+                                "Value at row {}, col {} is wrong, it should be {}",
+                                row + 1,
+                                col + 1,
+                                self.rows.shape().value_char(expected)
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Apply steps until a value is placed, candidate eliminations may be needed before it
+        let mut rows = self.rows.clone();
+        let mut candidates = sudoku::sudoku::candidates(&rows);
+        let mut cells: Vec<(u8, u8)> = Vec::new();
+        let mut techniques: Vec<&str> = Vec::new();
+        while let Some(deduction) = sudoku::sudoku::find_deduction(&rows, &candidates) {
+            sudoku::sudoku::apply_deduction(&mut rows, &mut candidates, &deduction);
+            for cell in deduction.cells.iter() {
+                if !cells.contains(cell) {
+                    cells.push(*cell);
+                }
+            }
+            if !techniques.contains(&deduction.technique.name()) {
+                techniques.push(deduction.technique.name());
+            }
+            if let Some(target) = deduction.placement {
+                return Some(Hint {
+                    cells,
+                    target,
+                    explanation: format!(
+                        "{}: {} goes to row {}, col {}",
+                        techniques.join(", "),
+                        self.rows.shape().value_char(target.2),
+                        target.0 + 1,
+                        target.1 + 1
+                    ),
+                });
+            }
+        }
+
+        // Techniques may not be enough for killer puzzles, the solution tells the next value then
+        if let Some(solution) = &self.solution {
+            for row in 0..size {
+                for col in 0..size {
+                    if self.rows[row as usize][col as usize] == 0 {
+                        let val = solution[row as usize][col as usize];
+                        return Some(Hint {
+                            cells: vec![(row, col)],
+                            target: (row, col, val),
+                            explanation: format!(
+                                "No logical step found, {} goes to row {}, col {}",
+                                self.rows.shape().value_char(val),
+                                row + 1,
+                                col + 1
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    fn show_hint(&mut self, hint: Option<&Hint>) {
+        let size = self.rows.shape().size as usize;
+        for row in 0..size {
+            for col in 0..size {
+                self.cell_data[row][col].hint = false;
+            }
+        }
+        if let Some(hint) = hint {
+            for &(row, col) in hint.cells.iter() {
+                self.cell_data[row as usize][col as usize].hint = true;
+            }
+        }
+    }
+
+    // @return false if there is nothing to undo
+    fn undo(&mut self) -> bool {
+        match self.undo_stack.pop() {
+            Some(data) => {
+                self.write_cell(data.cell, data.old_value, data.old_notes);
+                self.redo_stack.push(data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // @return false if there is nothing to redo
+    fn redo(&mut self) -> bool {
+        match self.redo_stack.pop() {
+            Some(data) => {
+                self.write_cell(data.cell, data.new_value, data.new_notes);
+                self.undo_stack.push(data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn givens(&self) -> Grid {
+        let mut givens = self.rows.clone();
+        let size = givens.shape().size as usize;
+        for row in 0..size {
+            for col in 0..size {
+                if self.cell_data[row][col].editable {
+                    givens[row][col] = 0;
+                }
+            }
+        }
+        givens
+    }
+
+    // Initial values with cages and current state as two lines in the format of parse_board
+    fn export(&self) -> String {
+        format!(
+            "{}\n{}\n",
+            sudoku::sudoku::format_puzzle(&self.givens()),
+            sudoku::sudoku::format_board(&self.rows)
+        )
+    }
+
+    // Whether player has entered any value or note
+    fn has_progress(&self) -> bool {
+        self.rows
+            .cells()
+            .iter()
+            .zip(self.cell_data.cells())
+            .any(|(val, data)| data.editable && (*val != 0 || data.notes != 0))
+    }
+
+    fn save(&self, elapsed_secs: u64, hints_used: u32) -> SavedGame {
+        let shape = self.rows.shape();
+        let mut notes: Grid<u32> = Grid::new(shape);
+        for row in 0..shape.size as usize {
+            for col in 0..shape.size as usize {
+                notes[row][col] = self.cell_data[row][col].notes;
+            }
+        }
+        SavedGame {
+            difficulty: self.difficulty,
+            seed: self.seed,
+            daily: self.daily,
+            givens: self.givens(),
+            values: self.rows.clone(),
+            notes,
+            undo_stack: self.undo_stack.clone(),
+            redo_stack: self.redo_stack.clone(),
+            elapsed_secs,
+            hints_used,
+        }
+    }
+
+    // Continues a saved game with its undo history
+    fn restore(&mut self, saved: &SavedGame) {
+        self.difficulty = saved.difficulty;
+        self.seed = saved.seed;
+        self.daily = saved.daily;
+        self.set_initial_rows(saved.givens.clone());
+        let size = self.rows.shape().size as usize;
+        for row in 0..size {
+            for col in 0..size {
+                if self.cell_data[row][col].editable {
+                    self.rows[row][col] = saved.values[row][col];
+                    self.cell_data[row][col].notes = saved.notes[row][col];
+                }
+            }
+        }
+        self.undo_stack = saved.undo_stack.clone();
+        self.redo_stack = saved.redo_stack.clone();
         self.update_cell_data();
     }
 
-    fn set_initial_rows(&mut self, rows: [[u8; 9]; 9]) {
+    fn set_initial_rows(&mut self, rows: Grid) {
+        let (_, solved_rows) = sudoku::sudoku::solve_logically(&rows);
+        self.solution = if sudoku::sudoku::is_finished(&solved_rows) {
+            Some(solved_rows)
+        } else {
+            // Given killer puzzles are rarely solved logically, their unique solution is searched
+            let solutions = sudoku::sudoku::find_solutions(&rows, 2);
+            (solutions.len() == 1).then(|| solutions[0].clone())
+        };
         self.rows = rows;
 
         // Init cell data
-        for row in 0..9 {
-            for col in 0..9 {
+        let size = self.rows.shape().size as usize;
+        self.cell_data = Grid::new(self.rows.shape());
+        for row in 0..size {
+            for col in 0..size {
                 self.cell_data[row][col].editable = self.rows[row][col] == 0;
             }
         }
@@ -186,42 +1054,617 @@ impl<'a> Board {
     }
 }
 
+// Data is kept in $XDG_DATA_HOME/sudoku-term, falling back to ~/.local/share/sudoku-term
+fn data_dir() -> Option<PathBuf> {
+    let data_dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data_dir.join("sudoku-term"))
+}
+
+// Completed daily puzzles, a line of date, elapsed seconds and hints used for each
+struct DailyRecords;
+
+impl DailyRecords {
+    fn path() -> Option<PathBuf> {
+        Some(data_dir()?.join("daily.txt"))
+    }
+
+    // @return elapsed seconds and hints used if daily puzzle of the date is completed
+    fn find(date: NaiveDate) -> Option<(u64, u32)> {
+        let text = fs::read_to_string(Self::path()?).ok()?;
+        let date = date.to_string();
+        text.lines().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next()? != date {
+                return None;
+            }
+            Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+        })
+    }
+
+    // Only the first completion of a date is recorded
+    fn record(date: NaiveDate, elapsed_secs: u64, hints_used: u32) -> io::Result<()> {
+        if Self::find(date).is_some() {
+            return Ok(());
+        }
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{} {} {}", date, elapsed_secs, hints_used)
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    if secs >= 120 {
+        format!("{} mins {} secs", secs / 60, secs % 60)
+    } else if secs >= 60 {
+        format!("1 min {} secs", secs % 60)
+    } else {
+        format!("{} secs", secs)
+    }
+}
+
+// State of an unfinished game, kept across sessions
+#[derive(Debug, PartialEq)]
+struct SavedGame {
+    difficulty: Option<Difficulty>,
+    seed: Option<u64>,
+    daily: Option<NaiveDate>,
+    givens: Grid,
+    values: Grid,
+    notes: Grid<u32>,
+    undo_stack: Vec<Move>,
+    redo_stack: Vec<Move>,
+    elapsed_secs: u64,
+    hints_used: u32,
+}
+
+impl SavedGame {
+    fn path() -> Option<PathBuf> {
+        Some(data_dir()?.join("save.txt"))
+    }
+
+    // A line of key=value for each field, in any order. Notes are numbers separated by commas,
+    // moves are row, col, old value, old notes, new value and new notes separated by semicolons.
+    fn to_text(&self) -> String {
+        let difficulty = match self.difficulty {
+            Some(difficulty) => format!("{:?}", difficulty),
+            None => String::from("Custom"),
+        };
+        let seed = match self.seed {
+            Some(seed) => seed.to_string(),
+            None => String::new(),
+        };
+        let daily = match self.daily {
+            Some(date) => date.to_string(),
+            None => String::new(),
+        };
+        let notes: Vec<String> = self
+            .notes
+            .cells()
+            .iter()
+            .map(|notes| notes.to_string())
+            .collect();
+        let moves = |stack: &[Move]| -> String {
+            stack
+                .iter()
+                .map(|data| {
+                    format!(
+                        "{},{},{},{},{},{}",
+                        data.cell.0,
+                        data.cell.1,
+                        data.old_value,
+                        data.old_notes,
+                        data.new_value,
+                        data.new_notes
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(";")
+        };
+        format!(
+            "difficulty={}\nseed={}\ndaily={}\ngivens={}\nvalues={}\nnotes={}\nundo={}\nredo={}\n\
+            elapsed={}\nhints={}\n",
+            difficulty,
+            seed,
+            daily,
+            sudoku::sudoku::format_puzzle(&self.givens),
+            sudoku::sudoku::format_board(&self.values),
+            notes.join(","),
+            moves(&self.undo_stack),
+            moves(&self.redo_stack),
+            self.elapsed_secs,
+            self.hints_used
+        )
+    }
+
+    // Keys are read first, so that the board is built whatever order they come in. Unknown
+    // keys are ignored, givens, values and notes are required.
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut fields: HashMap<&str, &str> = HashMap::new();
+        for line in text.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                fields.insert(key.trim(), value.trim());
+            }
+        }
+        let field = |key: &str| -> Result<&str, String> {
+            fields
+                .get(key)
+                .copied()
+                .ok_or(format!("{} is missing", key))
+        };
+
+        let givens = sudoku::sudoku::parse_board(field("givens")?)?;
+        let values = sudoku::sudoku::parse_board(field("values")?)?;
+        let shape = givens.shape();
+        if values.shape() != shape {
+            return Err(String::from("givens and values are of different sizes"));
+        }
+
+        let size = shape.size as usize;
+        let mut notes: Grid<u32> = Grid::new(shape);
+        let all_notes: Vec<&str> = field("notes")?.split(',').collect();
+        if all_notes.len() != shape.cell_count() {
+            return Err(format!(
+                "expected notes for each of {} cells, found {}",
+                shape.cell_count(),
+                all_notes.len()
+            ));
+        }
+        for (i, cell_notes) in all_notes.into_iter().enumerate() {
+            notes[i / size][i % size] = cell_notes
+                .parse()
+                .ok()
+                .filter(|notes| notes & !shape.all_values() == 0)
+                .ok_or(format!("invalid notes '{}'", cell_notes))?;
+        }
+
+        let parse_moves = |key: &str| -> Result<Vec<Move>, String> {
+            let text = fields.get(key).copied().unwrap_or_default();
+            text.split(';')
+                .filter(|data| !data.is_empty())
+                .map(|data| {
+                    let numbers: Vec<u32> = data
+                        .split(',')
+                        .map(|number| number.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| format!("invalid move '{}'", data))?;
+                    match numbers[..] {
+                        [row, col, old_value, old_notes, new_value, new_notes]
+                            if row < size as u32
+                                && col < size as u32
+                                && old_value <= size as u32
+                                && new_value <= size as u32
+                                && (old_notes | new_notes) & !shape.all_values() == 0 =>
+                        {
+                            Ok(Move {
+                                cell: (row as u8, col as u8),
+                                old_value: old_value as u8,
+                                old_notes,
+                                new_value: new_value as u8,
+                                new_notes,
+                            })
+                        }
+                        _ => Err(format!("invalid move '{}'", data)),
+                    }
+                })
+                .collect()
+        };
+
+        Ok(SavedGame {
+            difficulty: fields
+                .get("difficulty")
+                .and_then(|value| Difficulty::from_str(value, true).ok()),
+            seed: fields.get("seed").and_then(|value| value.parse().ok()),
+            daily: fields.get("daily").and_then(|value| value.parse().ok()),
+            givens,
+            values,
+            notes,
+            undo_stack: parse_moves("undo")?,
+            redo_stack: parse_moves("redo")?,
+            elapsed_secs: match fields.get("elapsed") {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| format!("invalid elapsed time '{}'", value))?,
+                None => 0,
+            },
+            hints_used: match fields.get("hints") {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| format!("invalid hint count '{}'", value))?,
+                None => 0,
+            },
+        })
+    }
+
+    fn load() -> Result<Self, String> {
+        let path = Self::path().ok_or("can't find data directory")?;
+        let text = fs::read_to_string(&path)
+            .map_err(|err| format!("can't read {}: {}", path.display(), err))?;
+        Self::from_text(&text)
+    }
+
+    fn store(&self) -> io::Result<()> {
+        if let Some(path) = Self::path() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, self.to_text())?;
+        }
+        Ok(())
+    }
+
+    fn exists() -> bool {
+        Self::path().is_some_and(|path| path.exists())
+    }
+
+    fn remove() {
+        if let Some(path) = Self::path() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Puzzle is the first non-empty line of a file, so that files written by Board::export can
+// be played, the progress on the next line is ignored
+fn puzzle_of_file(text: &str) -> &str {
+    text.lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+}
+
+// How the game starts
+enum Game {
+    Generated(Difficulty, sudoku::sudoku::GenerationOptions),
+    Daily(NaiveDate, sudoku::sudoku::GenerationOptions),
+    Given(Grid),
+    Saved(Box<SavedGame>),
+}
+
+// Exit statuses of solve command, the highest one among all puzzles is used
+const EXIT_MULTIPLE_SOLUTIONS: i32 = 3;
+const EXIT_NO_SOLUTION: i32 = 4;
+const EXIT_MALFORMED_INPUT: i32 = 5;
+
+// Solutions are counted up to this number, since sparse puzzles can have millions of them
+const SOLUTION_COUNT_LIMIT: usize = 1000;
+
+// Prints a line for each puzzle: the solution, or why it can't be solved
+// @return exit status
+fn solve_puzzles(file: Option<PathBuf>, solver: SolverBackend, threads: usize) -> i32 {
+    let text = match &file {
+        Some(path) => fs::read_to_string(path),
+        None => io::read_to_string(io::stdin()),
+    };
+    let text = match text {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Can't read puzzles: {}", err);
+            return EXIT_MALFORMED_INPUT;
+        }
+    };
+
+    // Puzzles are solved in parallel, results are still printed in order of lines
+    let jobs: Vec<_> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| -> sudoku::sudoku::Job<_> {
+            Box::new(move || {
+                sudoku::sudoku::parse_board(line).map(|board| {
+                    sudoku::sudoku::find_solutions_with(
+                        solver.solver(&board),
+                        &board,
+                        SOLUTION_COUNT_LIMIT,
+                    )
+                })
+            })
+        })
+        .collect();
+
+    let mut status = 0;
+    for result in sudoku::sudoku::run_jobs(jobs, threads) {
+        let solutions = match result {
+            Ok(solutions) => solutions,
+            Err(err) => {
+                println!("invalid puzzle: {}", err);
+                status = status.max(EXIT_MALFORMED_INPUT);
+                continue;
+            }
+        };
+        match solutions.len() {
+            0 => {
+                println!("no solution");
+                status = status.max(EXIT_NO_SOLUTION);
+            }
+            1 => println!("{}", sudoku::sudoku::format_board(&solutions[0])),
+            SOLUTION_COUNT_LIMIT => {
+                println!("multiple solutions (at least {})", SOLUTION_COUNT_LIMIT);
+                status = status.max(EXIT_MULTIPLE_SOLUTIONS);
+            }
+            count => {
+                println!("multiple solutions ({})", count);
+                status = status.max(EXIT_MULTIPLE_SOLUTIONS);
+            }
+        }
+    }
+
+    status
+}
+
+// Prints given number of distinct puzzles as they are generated
+// Seed of each puzzle is drawn from the seed of options, so the batch can be reproduced
+fn generate_puzzles(
+    count: usize,
+    difficulty: Difficulty,
+    format: OutputFormat,
+    options: sudoku::sudoku::GenerationOptions,
+) {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut puzzles: HashSet<Grid> = HashSet::new();
+    if format == OutputFormat::Json {
+        println!("[");
+    }
+    while puzzles.len() < count {
+        let seed: u64 = rng.random();
+        let (rating, board) = sudoku::sudoku::generate_initial_board(
+            &sudoku::sudoku::GenerationOptions {
+                seed,
+                ..options.clone()
+            },
+            &sudoku::sudoku::CancelToken::new(),
+            None,
+        )
+        .expect("generation is never cancelled");
+        if !puzzles.insert(board.clone()) {
+            continue;
+        }
+        // Puzzles in band and killer ones are solved logically, others may need a search
+        let (_, mut solution) = sudoku::sudoku::solve_logically(&board);
+        if !sudoku::sudoku::is_finished(&solution) {
+            solution = sudoku::sudoku::find_solutions(&board, 1).remove(0);
+        }
+        let hardest = rating.hardest.map_or("", |technique| technique.name());
+        // Killer puzzles aren't generated for a band, others are out of it only when time
+        // budget is exceeded or the band isn't reached after many boards, which is warned about
+        // so that a batch isn't silently mislabeled
+        let in_band = !board.cages().is_empty() || rating.is_in(&options.band);
+        if !in_band {
+            eprintln!(
+                "Difficulty not reached, puzzle {} {}",
+                puzzles.len(),
+                difficulty.describe_out_of_band(&board, &rating, &options.band)
+            );
+        }
+        match format {
+            OutputFormat::Line => println!(
+                "{} {} {} {}",
+                sudoku::sudoku::format_puzzle(&board),
+                sudoku::sudoku::format_board(&solution),
+                rating.score,
+                hardest
+            ),
+            OutputFormat::Json => println!(
+                "  {{\"puzzle\": \"{}\", \"solution\": \"{}\", \"difficulty\": \"{:?}\", \
+                \"in_band\": {}, \"seed\": {}, \"hardest\": \"{}\", \"hardest_count\": {}, \
+                \"score\": {}}}{}",
+                sudoku::sudoku::format_puzzle(&board),
+                sudoku::sudoku::format_board(&solution),
+                difficulty,
+                in_band,
+                seed,
+                hardest,
+                rating.hardest_count,
+                rating.score,
+                if puzzles.len() < count { "," } else { "" }
+            ),
+        }
+    }
+    if format == OutputFormat::Json {
+        println!("]");
+    }
+}
+
+// First argument given on command line which the subcommand ignores, with the subcommand name
+fn ignored_arg(matches: &clap::ArgMatches) -> Option<(String, &str)> {
+    let name = matches.subcommand_name()?;
+    let command = Command::from_arg_matches(matches).ok()?;
+    Args::command()
+        .get_arguments()
+        .filter(|arg| command.ignored_args().contains(&arg.get_id().as_str()))
+        .find(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .map(|arg| match arg.get_long() {
+            Some(long) => (format!("--{}", long), name),
+            None => (format!("[{}]", arg.get_id().as_str().to_uppercase()), name),
+        })
+}
+
 fn main() -> io::Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let threads = args.thread_count();
+    if let Some((arg, command)) = ignored_arg(&matches) {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!("the argument '{}' cannot be used with '{}'", arg, command),
+            )
+            .exit();
+    }
+    // Searching large killer boards for uniqueness takes too long
+    if args.variant == GameVariant::Killer && args.size > 9 {
+        eprintln!("Killer puzzles can't be larger than 9x9");
+        process::exit(1);
+    }
+    // Some random regions have no solution, and proving it takes too long on large boards
+    if args.jigsaw && args.size > 12 {
+        eprintln!("Jigsaw puzzles can't be larger than 12x12");
+        process::exit(1);
+    }
+    if args.jigsaw && !args.constraint.is_empty() && args.size > 9 {
+        eprintln!("Jigsaw puzzles with constraints can't be larger than 9x9");
+        process::exit(1);
+    }
+
+    match args.command {
+        Some(Command::Solve { file, solver }) => {
+            process::exit(solve_puzzles(file, solver, threads))
+        }
+        Some(Command::Generate {
+            count,
+            difficulty,
+            format,
+        }) => {
+            generate_puzzles(
+                count,
+                difficulty,
+                format,
+                args.generation_options(
+                    difficulty,
+                    seed,
+                    args.shape(),
+                    args.variant,
+                    &args.constraint,
+                    args.jigsaw,
+                ),
+            );
+            return Ok(());
+        }
+        Some(Command::Daily) | None => {}
+    }
+
+    let puzzle_text = match (&args.puzzle, &args.file) {
+        (Some(text), _) => Some(text.clone()),
+        (None, Some(path)) => match fs::read_to_string(path) {
+            Ok(text) => Some(puzzle_of_file(&text).to_string()),
+            Err(err) => {
+                eprintln!("Can't read {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        },
+        (None, None) => None,
+    };
+    let puzzle = puzzle_text.map(|text| {
+        match sudoku::sudoku::parse_board(&text)
+            .and_then(|board| sudoku::sudoku::validate_puzzle(&board).map(|_| board))
+        {
+            Ok(board) => board,
+            Err(err) => {
+                eprintln!("Invalid puzzle: {}", err);
+                process::exit(1);
+            }
+        }
+    });
+
+    let game = if let Some(Command::Daily) = args.command {
+        // Everyone gets the same puzzle on the same date, since seed is derived from it
+        let date = Local::now().date_naive();
+        let seed = date.year() as u64 * 10000 + date.month() as u64 * 100 + date.day() as u64;
+        let difficulty = Difficulty::of_weekday(date.weekday());
+        let mut options = args.generation_options(
+            difficulty,
+            seed,
+            Shape::CLASSIC,
+            GameVariant::Classic,
+            &[],
+            false,
+        );
+        // Exceeding time budget would change the board, boards are then chosen by how fast the
+        // machine is
+        options.time_budget = None;
+        Game::Daily(date, options)
+    } else if args.resume {
+        match SavedGame::load() {
+            Ok(saved) => Game::Saved(Box::new(saved)),
+            Err(err) => {
+                eprintln!("Can't resume: {}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        match puzzle {
+            Some(board) => Game::Given(board),
+            None => Game::Generated(
+                args.difficulty,
+                args.generation_options(
+                    args.difficulty,
+                    seed,
+                    args.shape(),
+                    args.variant,
+                    &args.constraint,
+                    args.jigsaw,
+                ),
+            ),
+        }
+    };
+
+    if args.print_puzzle {
+        // Daily puzzles are printed as played, so the game is resolved first
+        let board = match game {
+            Game::Generated(_, options) | Game::Daily(_, options) => {
+                let (_, board) = sudoku::sudoku::generate_initial_board(
+                    &options,
+                    &sudoku::sudoku::CancelToken::new(),
+                    None,
+                )
+                .expect("generation is never cancelled");
+                board
+            }
+            Game::Given(board) => board,
+            Game::Saved(_) => unreachable!("--resume conflicts with --print-puzzle"),
+        };
+        println!("{}", sudoku::sudoku::format_puzzle(&board));
+        return Ok(());
+    }
 
     let mut terminal = ratatui::init();
     terminal.clear()?;
 
-    let app_result = run(terminal, args.difficulty, args.hide_elapsed_time);
+    let app_result = run(terminal, game, args.hide_elapsed_time, args.export);
     ratatui::restore();
-    app_result
+    if let Ok(Some(export)) = &app_result {
+        print!("{}", export);
+    }
+    app_result.map(|_| ())
 }
 
-fn run(
-    mut terminal: DefaultTerminal,
-    difficulty: Difficulty,
-    hide_elapsed_time: bool,
-) -> io::Result<()> {
-    let mut board: Board = Board::new(difficulty);
-
-    let difficulty_val = match difficulty {
-        Difficulty::Easy => 100,
-        Difficulty::Medium => 140,
-        Difficulty::Hard => 160,
-    };
+// Generates a board in background while animating a loading text and showing progress.
+// Returns None if generation is cancelled with q or Esc.
+fn generate_board(
+    terminal: &mut DefaultTerminal,
+    options: sudoku::sudoku::GenerationOptions,
+) -> io::Result<Option<(sudoku::sudoku::Rating, Grid)>> {
+    let cell_count = options.shape.cell_count();
+    let cancel = sudoku::sudoku::CancelToken::new();
+    let (sender, receiver) = mpsc::channel::<sudoku::sudoku::Progress>();
 
     // Start the thread which creates the initial board here.
-    let init_thread_handle =
-        thread::spawn(move || sudoku::sudoku::generate_initial_board(difficulty_val));
+    let thread_cancel = cancel.clone();
+    let init_thread_handle = thread::spawn(move || {
+        sudoku::sudoku::generate_initial_board(&options, &thread_cancel, Some(sender))
+    });
 
     // The loop until initial board is created
     let mut counter = 0;
-    let board_generation_start_time = Instant::now();
+    let mut progress: Option<sudoku::sudoku::Progress> = None;
     loop {
         if init_thread_handle.is_finished() {
             break;
         }
 
+        // Boards are processed in parallel, only the latest report is shown
+        progress = receiver.try_iter().last().or(progress);
+
         // Animate text
         let text = "Generating board ... ";
         let shift = counter % text.len();
@@ -232,33 +1675,170 @@ fn run(
                 Text::from(print_text).centered(),
                 Rect::new(0, frame.area().height / 2, frame.area().width, 1),
             );
-            if board_generation_start_time.elapsed() > Duration::from_secs(10) {
+            if let Some(progress) = progress {
+                let hardest = progress
+                    .rating
+                    .hardest
+                    .map_or("-", |technique| technique.name());
                 frame.render_widget(
-                    Text::from("It may take time depending on difficulty").centered(),
-                    Rect::new(0, frame.area().height - 3, frame.area().width, 1),
+                    Text::from(format!(
+                        "Board {}, tried {}/{} cells, {} clues left, hardest: {}",
+                        progress.attempt, progress.tried_cells, cell_count, progress.clues, hardest
+                    ))
+                    .centered(),
+                    Rect::new(0, frame.area().height / 2 + 2, frame.area().width, 1),
                 );
             }
+            frame.render_widget(
+                Text::from("q, Esc: Cancel").centered(),
+                Rect::new(0, frame.area().height - 3, frame.area().width, 1),
+            );
         })?;
 
-        thread::sleep(Duration::from_millis(100));
-        counter = counter + 1;
+        if let Ok(true) = event::poll(Duration::from_millis(100)) {
+            if let event::Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press
+                    && (key.code == KeyCode::Char('q') || key.code == KeyCode::Esc)
+                {
+                    // Generator checks the token between cell removals and while searching solved
+                    // boards, so it stops shortly
+                    cancel.cancel();
+                    let _ = init_thread_handle.join();
+                    return Ok(None);
+                }
+            }
+        }
+        counter += 1;
+    }
+
+    Ok(init_thread_handle.join().unwrap())
+}
+
+fn run(
+    mut terminal: DefaultTerminal,
+    game: Game,
+    hide_elapsed_time: bool,
+    export_path: Option<PathBuf>,
+) -> io::Result<Option<String>> {
+    let mut board = Board::new(None);
+    let mut elapsed = Duration::ZERO;
+    let mut hints_used = 0;
+    let mut message = String::new();
+    // Game in the save file, which is replaced or removed only by the same game
+    let resumed = matches!(game, Game::Saved(_));
+    // Difficulty and options of the board to be generated
+    let generation = match game {
+        Game::Generated(difficulty, options) => Some((difficulty, options)),
+        Game::Daily(date, options) => {
+            board.daily = Some(date);
+            Some((Difficulty::of_weekday(date.weekday()), options))
+        }
+        Game::Given(rows) => {
+            board.set_initial_rows(rows);
+            None
+        }
+        Game::Saved(saved) => {
+            board.restore(&saved);
+            elapsed = Duration::from_secs(saved.elapsed_secs);
+            hints_used = saved.hints_used;
+            None
+        }
+    };
+    if let Some((difficulty, options)) = generation {
+        board.difficulty = Some(difficulty);
+        board.seed = Some(options.seed);
+        let band = options.band.clone();
+        match generate_board(&mut terminal, options)? {
+            Some((rating, rows)) => {
+                // Killer puzzles aren't generated for a band
+                let killer = !rows.cages().is_empty();
+                if !killer && !rating.is_in(&band) {
+                    message = format!(
+                        "Difficulty not reached, puzzle {}",
+                        difficulty.describe_out_of_band(&rows, &rating, &band)
+                    );
+                }
+                board.set_initial_rows(rows);
+            }
+            None => return Ok(None),
+        }
+    }
+
+    if !resumed && message.is_empty() && SavedGame::exists() {
+        message = String::from(
+            "A saved game is replaced if this one is quit with moves, --resume continues it",
+        );
     }
-    board.set_initial_rows(init_thread_handle.join().unwrap());
 
     // The game loop
-    let start_time = Instant::now();
+    // Elapsed time of a resumed game is counted in by starting earlier
+    let start_time = Instant::now()
+        .checked_sub(elapsed)
+        .unwrap_or(Instant::now());
     let mut finish_time = start_time;
-    let mut finished = false;
-    let mut undo_data: Option<(u8, u8, u8)> = Option::None; // row, col, val
+    let mut finished = sudoku::sudoku::is_finished(&board.rows);
+    let mut hint: Option<Hint> = None;
+    if let Some((secs, hints)) = board.daily.and_then(DailyRecords::find) {
+        message = format!(
+            "Today's puzzle is already completed in {}, hints: {}",
+            format_duration(secs),
+            hints
+        );
+    }
+    // Board is exported on exit, with moves made after the export key too
+    let mut export_on_exit = false;
+    // Set once saving on quit fails, so that quitting again doesn't try to save
+    let mut save_failed = false;
     loop {
         terminal.draw(|frame| {
+            board.screen_size = (frame.area().width, frame.area().height);
+            let (cell_width, cell_height) = board.cell_size();
+            let size = board.rows.shape().size as u16;
+            let (width, height) = (size * cell_width, size * cell_height);
             let board_rect = Rect::new(
-                (frame.area().width as u16 - 27) / 2,
-                (frame.area().height as u16 - 9) / 2,
-                27,
-                9,
+                frame.area().width.saturating_sub(width) / 2,
+                // Leave room for the message, status and key help lines below the board
+                frame.area().height.saturating_sub(height + 3) / 2,
+                width,
+                height,
             );
             frame.render_widget(board.create_table(), board_rect);
+            // Compact cells have no room for notes and cage sums, those of current cell are
+            // shown below. Cage sums are shown for other boards too, since notes may cover them.
+            let (row, col) = board.current_cell;
+            let notes = board.cell_data[row as usize][col as usize].notes;
+            let mut info: Vec<String> = Vec::new();
+            if let Some(cage) = board.rows.cage_at(row, col) {
+                info.push(format!("Cage sum: {}", cage.sum));
+            }
+            if board.is_compact() && notes != 0 && board.rows[row as usize][col as usize] == 0 {
+                let notes: String = (1..=size as u8)
+                    .filter(|val| notes & (1 << val) != 0)
+                    .map(|val| board.rows.shape().value_char(val))
+                    .collect();
+                info.push(format!("Notes: {}", notes));
+            }
+            if !info.is_empty() {
+                frame.render_widget(
+                    Text::from(info.join(", ")).centered(),
+                    Rect::new(
+                        0,
+                        (board_rect.y + height).min(frame.area().height.saturating_sub(4)),
+                        frame.area().width,
+                        1,
+                    ),
+                );
+            }
+            // Message, status and key help each get a line at the bottom, the status line is split
+            // so that the difficulty label and the timer don't overwrite each other
+            let [message_area, status_area, keys_area] =
+                Layout::vertical([Constraint::Length(1); 3]).areas(Rect::new(
+                    0,
+                    frame.area().height.saturating_sub(3),
+                    frame.area().width,
+                    frame.area().height.min(3),
+                ));
+            let mut time_label = String::new();
             if !hide_elapsed_time {
                 let secs = {
                     if start_time == finish_time {
@@ -267,99 +1847,437 @@ fn run(
                         finish_time.duration_since(start_time).as_secs()
                     }
                 };
-                let mut time_label = Text::from(format!("{} secs", secs)).right_aligned();
-                if secs >= 60 {
-                    if secs >= 120 {
-                        time_label = Text::from(format!("{} mins {} secs", secs / 60, secs % 60))
-                            .right_aligned();
-                    } else {
-                        time_label =
-                            Text::from(format!("1 min {} secs", secs % 60)).right_aligned();
-                    }
+                time_label = format_duration(secs);
+            }
+            if hints_used > 0 {
+                if !time_label.is_empty() {
+                    time_label.push_str(", ");
                 }
-                frame.render_widget(
-                    time_label,
-                    Rect::new(
-                        frame.area().width / 2,
-                        frame.area().height - 1,
-                        frame.area().width / 2,
-                        1,
-                    ),
-                );
+                time_label.push_str(&format!("hints: {}", hints_used));
             }
-            let difficulty_label = Text::from(format!("{:?}", board.difficulty)).left_aligned();
-            frame.render_widget(
-                difficulty_label,
-                Rect::new(0, frame.area().height - 1, frame.area().width / 2, 1),
-            );
-            frame.render_widget(
-                Text::from("d: delete, u: undo, q: quit").centered(),
-                Rect::new(0, frame.area().height - 1, frame.area().width, 1),
-            );
+            frame.render_widget(Text::from(message.as_str()).centered(), message_area);
+            let mut difficulty_label = match board.difficulty {
+                Some(difficulty) => format!("{:?}", difficulty),
+                None => String::from("Custom"),
+            };
+            let size = board.rows.shape().size;
+            if !board.rows.cages().is_empty() {
+                difficulty_label.push_str(" Killer");
+            }
+            if board.rows.regions().is_some() {
+                difficulty_label.push_str(" Jigsaw");
+            }
+            for constraint in board.rows.constraints() {
+                difficulty_label.push_str(&format!(" {}", constraint.name()));
+            }
+            if size != 9 {
+                difficulty_label.push_str(&format!(" {}x{}", size, size));
+            }
+            if let Some(seed) = board.seed {
+                difficulty_label.push_str(&format!(", seed {}", seed));
+            }
+            if board.notes_mode {
+                difficulty_label.push_str(" [notes]");
+            }
+            let [label_area, time_area] = Layout::horizontal([
+                Constraint::Min(0),
+                Constraint::Length(time_label.chars().count() as u16),
+            ])
+            .spacing(1)
+            .areas(status_area);
+            frame.render_widget(Text::from(difficulty_label).left_aligned(), label_area);
+            frame.render_widget(Text::from(time_label).right_aligned(), time_area);
+            let mut keys =
+                String::from("d: delete, n: notes, u: undo, r: redo, h: hint, e: export, q: quit");
+            // Lower case d and e are commands, so values D and E of 16x16 boards need Shift
+            if board.rows.shape().char_value('D').is_some() {
+                keys.insert_str(0, "Shift+D/E: values D/E, ");
+            }
+            frame.render_widget(Text::from(keys).centered(), keys_area);
         })?;
 
-        match event::poll(Duration::from_millis(200)) {
-            Ok(true) => {
-                if let event::Event::Key(key) = event::read()? {
-                    if key.kind == KeyEventKind::Press {
-                        if key.code == KeyCode::Char('q') {
-                            return Ok(());
-                        } else if finished {
-                            continue;
-                        } else if key.code == KeyCode::Char('u') {
-                            match undo_data {
-                                Some(data) => {
-                                    board.set_current(data.0, data.1);
-                                    board.set_value(data.2);
-                                }
-                                None => {}
+        if let Ok(true) = event::poll(Duration::from_millis(200)) {
+            if let event::Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    if key.code == KeyCode::Char('q') {
+                        // Saved game of an earlier sitting is kept until this one has progress
+                        if finished {
+                            if resumed {
+                                SavedGame::remove();
                             }
-                            undo_data = Option::None;
-                        } else if key.code == KeyCode::Char('d') {
-                            if board.cell_data[board.current_cell.0 as usize]
-                                [board.current_cell.1 as usize]
-                                .editable
-                            {
-                                board.set_value(0);
+                        } else if (resumed || board.has_progress()) && !save_failed {
+                            let saved = board.save(start_time.elapsed().as_secs(), hints_used);
+                            if let Err(err) = saved.store() {
+                                save_failed = true;
+                                message = format!("Can't save game: {}, q to quit anyway", err);
+                                continue;
                             }
-                        } else if key.code == KeyCode::Right {
-                            board.set_current(board.current_cell.0, (board.current_cell.1 + 1) % 9);
-                        } else if key.code == KeyCode::Left {
-                            if board.current_cell.1 == 0 {
-                                board.set_current(board.current_cell.0, 8);
-                            } else {
-                                board.set_current(board.current_cell.0, board.current_cell.1 - 1);
+                        }
+                        return Ok(export_on_exit.then(|| board.export()));
+                    } else if key.code == KeyCode::Char('e') {
+                        match &export_path {
+                            Some(path) => {
+                                message = match fs::write(path, board.export()) {
+                                    Ok(_) => format!("Exported to {}", path.display()),
+                                    Err(err) => format!("Can't export: {}", err),
+                                };
                             }
-                        } else if key.code == KeyCode::Up {
-                            if board.current_cell.0 == 0 {
-                                board.set_current(8, board.current_cell.1);
-                            } else {
-                                board.set_current(board.current_cell.0 - 1, board.current_cell.1);
+                            None => {
+                                export_on_exit = true;
+                                message = String::from("Puzzle will be printed on exit");
                             }
-                        } else if key.code == KeyCode::Down {
-                            board.set_current((board.current_cell.0 + 1) % 9, board.current_cell.1);
-                        } else if key.code >= KeyCode::Char('1') && key.code <= KeyCode::Char('9') {
-                            if board.cell_data[board.current_cell.0 as usize]
-                                [board.current_cell.1 as usize]
-                                .editable
-                            {
-                                undo_data = Some((
-                                    board.current_cell.0,
-                                    board.current_cell.1,
-                                    board.rows[board.current_cell.0 as usize]
-                                        [board.current_cell.1 as usize],
-                                ));
-                                board.set_value(key.code.to_string().parse().unwrap());
-                                finished = sudoku::sudoku::is_finished(&board.rows);
-                                if finished {
-                                    finish_time = Instant::now();
+                        }
+                        continue;
+                    } else if finished {
+                        continue;
+                    }
+
+                    let size = board.rows.shape().size;
+
+                    // Any other key dismisses the pending hint
+                    if key.code != KeyCode::Char('h') {
+                        hint = None;
+                        board.show_hint(None);
+                        message.clear();
+                    }
+
+                    if key.code == KeyCode::Char('h') {
+                        match hint.take() {
+                            None => {
+                                hint = board.find_hint();
+                                match hint {
+                                    Some(_) => {
+                                        hints_used += 1;
+                                        message = String::from("Press h again for explanation");
+                                    }
+                                    None => message = String::from("No hint available"),
                                 }
+                                board.show_hint(hint.as_ref());
+                            }
+                            Some(pending_hint) => {
+                                let (row, col, val) = pending_hint.target;
+                                board.show_hint(None);
+                                board.set_current(row, col);
+                                board.set_value(val);
+                                message = pending_hint.explanation;
+                            }
+                        }
+                    } else if key.code == KeyCode::Char('u') {
+                        board.undo();
+                    } else if key.code == KeyCode::Char('r') {
+                        board.redo();
+                    } else if key.code == KeyCode::Char('n') {
+                        board.notes_mode = !board.notes_mode;
+                    } else if key.code == KeyCode::Char('d') {
+                        if board.cell_data[board.current_cell.0 as usize]
+                            [board.current_cell.1 as usize]
+                            .editable
+                        {
+                            board.clear_cell();
+                        }
+                    } else if key.code == KeyCode::Right {
+                        board.set_current(board.current_cell.0, (board.current_cell.1 + 1) % size);
+                    } else if key.code == KeyCode::Left {
+                        if board.current_cell.1 == 0 {
+                            board.set_current(board.current_cell.0, size - 1);
+                        } else {
+                            board.set_current(board.current_cell.0, board.current_cell.1 - 1);
+                        }
+                    } else if key.code == KeyCode::Up {
+                        if board.current_cell.0 == 0 {
+                            board.set_current(size - 1, board.current_cell.1);
+                        } else {
+                            board.set_current(board.current_cell.0 - 1, board.current_cell.1);
+                        }
+                    } else if key.code == KeyCode::Down {
+                        board.set_current((board.current_cell.0 + 1) % size, board.current_cell.1);
+                    } else if let Some(val) = match key.code {
+                        // Letters are values in either case, as in puzzle text, unless they are
+                        // commands above
+                        KeyCode::Char(c) => board.rows.shape().char_value(c.to_ascii_uppercase()),
+                        _ => None,
+                    } {
+                        if !board.cell_data[board.current_cell.0 as usize]
+                            [board.current_cell.1 as usize]
+                            .editable
+                        {
+                            continue;
+                        }
+                        if board.notes_mode {
+                            board.toggle_note(val);
+                        } else {
+                            board.set_value(val);
+                        }
+                    }
+
+                    if sudoku::sudoku::is_finished(&board.rows) {
+                        finished = true;
+                        finish_time = Instant::now();
+                        if let Some(date) = board.daily {
+                            let secs = finish_time.duration_since(start_time).as_secs();
+                            if let Err(err) = DailyRecords::record(date, secs, hints_used) {
+                                message = format!("Can't record today's puzzle: {}", err);
                             }
                         }
                     }
                 }
             }
-            _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &str =
+        "53..7....6..195....98....6.8...6...34..8.3..17...2...6.6....28....419..5....8..79";
+
+    fn board_of(puzzle: &str) -> Board {
+        let mut board = Board::new(None);
+        board.set_initial_rows(sudoku::sudoku::parse_board(puzzle).unwrap());
+        board
+    }
+
+    // Notes and values are undone as single moves in reverse order
+    #[test]
+    fn undo_and_redo() {
+        let mut board = board_of(PUZZLE);
+        assert!(!board.undo());
+        board.set_current(0, 2);
+        board.toggle_note(1);
+        board.toggle_note(4);
+        board.set_value(4);
+        // Setting the same value again isn't a move
+        board.set_value(4);
+        assert_eq!(board.undo_stack.len(), 3);
+
+        assert!(board.undo());
+        assert_eq!(board.rows[0][2], 0);
+        assert_eq!(board.cell_data[0][2].notes, 1 << 1 | 1 << 4);
+        assert!(board.undo());
+        assert_eq!(board.cell_data[0][2].notes, 1 << 1);
+        assert!(board.redo() && board.redo());
+        assert_eq!(board.rows[0][2], 4);
+        assert!(!board.redo());
+
+        // Undoing moves the cursor to the changed cell, a new move drops moves undone before it
+        board.set_current(1, 1);
+        assert!(board.undo() && board.undo());
+        assert_eq!(board.current_cell, (0, 2));
+        board.set_current(1, 1);
+        board.set_value(7);
+        assert!(!board.redo());
+        assert_eq!((board.rows[0][2], board.rows[1][1]), (0, 7));
+        assert!(board.undo() && board.undo());
+        assert_eq!(board.rows[1][1], 0);
+        assert_eq!(board.cell_data[0][2].notes, 0);
+        assert!(!board.undo());
+    }
+
+    const SOLUTION: &str =
+        "534678912672195348198342567859761423426853791713924856961537284287419635345286179";
+
+    // Top line of the cell at row and col of the board as drawn on a 45x30 screen
+    fn top_line(board: &mut Board, row: u16, col: u16) -> String {
+        board.screen_size = (45, 30);
+        let area = Rect::new(0, 0, 45, 27);
+        let mut buffer = ratatui::buffer::Buffer::empty(area);
+        ratatui::widgets::Widget::render(board.create_table(), area, &mut buffer);
+        (col * 5..col * 5 + 5)
+            .map(|x| buffer[(x, row * 3)].symbol())
+            .collect()
+    }
+
+    // Subcommands reject the arguments of Args they don't use, wherever they are given
+    #[test]
+    fn ignored_args() {
+        let ignored = |args: &[&str]| {
+            let matches = Args::command().try_get_matches_from(args).unwrap();
+            ignored_arg(&matches).map(|(arg, command)| format!("{} {}", arg, command))
+        };
+        assert_eq!(
+            ignored(&["sudoku", "daily", "--seed", "3"]).as_deref(),
+            Some("--seed daily")
+        );
+        assert_eq!(
+            ignored(&["sudoku", "--size", "9", "daily"]).as_deref(),
+            Some("--size daily")
+        );
+        assert_eq!(
+            ignored(&["sudoku", "hard", "daily"]).as_deref(),
+            Some("[DIFFICULTY] daily")
+        );
+        assert_eq!(
+            ignored(&["sudoku", "generate", "--print-puzzle"]).as_deref(),
+            Some("--print-puzzle generate")
+        );
+        assert_eq!(
+            ignored(&["sudoku", "solve", "--jigsaw"]).as_deref(),
+            Some("--jigsaw solve")
+        );
+        assert_eq!(
+            ignored(&["sudoku", "daily", "--print-puzzle", "--threads", "2"]),
+            None
+        );
+        assert_eq!(ignored(&["sudoku", "--seed", "3", "generate"]), None);
+        assert_eq!(ignored(&["sudoku", "--seed", "3", "hard"]), None);
+    }
+
+    // Cage sum is drawn in the top line of the first cell of the cage, unless notes are there
+    #[test]
+    fn cage_sum_and_notes() {
+        let mut board = board_of(&format!("{}|10:2,3", PUZZLE));
+        assert_eq!(top_line(&mut board, 0, 2), "10┈┈┈");
+        board.set_current(0, 2);
+        board.toggle_note(1);
+        board.toggle_note(2);
+        assert_eq!(top_line(&mut board, 0, 2), "┌12┈┈");
+        board.toggle_note(1);
+        board.toggle_note(2);
+        board.toggle_note(3);
+        assert_eq!(top_line(&mut board, 0, 2), "10┈3┈");
+    }
+
+    #[test]
+    fn hints() {
+        let solution = sudoku::sudoku::parse_board(SOLUTION).unwrap();
+        let mut board = board_of(PUZZLE);
+        let hint = board.find_hint().unwrap();
+        let (row, col, val) = hint.target;
+        assert_eq!(board.rows[row as usize][col as usize], 0);
+        assert_eq!(solution[row as usize][col as usize], val);
+        assert!(hint.cells.contains(&(row, col)));
+        assert!(hint.explanation.starts_with("Naked single: "));
+
+        // Wrong values are told before any logical step
+        board.set_current(0, 2);
+        board.set_value(1);
+        let hint = board.find_hint().unwrap();
+        assert_eq!(hint.target, (0, 2, 4));
+        assert_eq!(
+            hint.explanation,
+            "Value at row 1, col 3 is wrong, it should be 4"
+        );
+
+        // Following hints solves the board
+        for _ in 0..81 {
+            let Some(hint) = board.find_hint() else {
+                break;
+            };
+            let (row, col, val) = hint.target;
+            board.set_current(row, col);
+            board.set_value(val);
+        }
+        assert_eq!(board.rows, solution);
+        assert!(board.find_hint().is_none());
+    }
+
+    // Givens keep the rules of the puzzle, progress is values only. Exported puzzle can be
+    // played with --file.
+    #[test]
+    fn export() {
+        let mut board = board_of(PUZZLE);
+        board.set_current(0, 2);
+        board.set_value(4);
+        board.toggle_note(1);
+        let progress = format!("{}4{}", &PUZZLE[..2], &PUZZLE[3..]);
+        assert_eq!(board.export(), format!("{}\n{}\n", PUZZLE, progress));
+
+        let puzzle = "...4............|3:0,1;7:2,6";
+        assert_eq!(
+            board_of(puzzle).export(),
+            format!("{}\n...4............\n", puzzle)
+        );
+
+        for board in [board, board_of(puzzle)] {
+            let export = format!("\n{}", board.export());
+            assert_eq!(
+                sudoku::sudoku::parse_board(puzzle_of_file(&export)),
+                Ok(board.givens())
+            );
+        }
+    }
+
+    #[test]
+    fn saved_games_round_trip() {
+        let mut board = board_of(PUZZLE);
+        board.difficulty = Some(Difficulty::Hard);
+        board.seed = Some(42);
+        board.set_current(0, 2);
+        board.set_value(4);
+        board.set_current(0, 3);
+        board.toggle_note(6);
+        board.toggle_note(2);
+        board.set_current(8, 0);
+        board.set_value(3);
+        board.undo();
+
+        let saved = board.save(95, 2);
+        let text = saved.to_text();
+        assert_eq!(SavedGame::from_text(&text).unwrap(), saved);
+
+        // Keys may come in any order
+        let reversed: Vec<&str> = text.lines().rev().collect();
+        assert_eq!(SavedGame::from_text(&reversed.join("\n")).unwrap(), saved);
+
+        let mut restored = Board::new(None);
+        restored.restore(&saved);
+        assert_eq!(restored.rows, board.rows);
+        assert_eq!(restored.cell_data[0][3].notes, 1 << 2 | 1 << 6);
+        assert_eq!(restored.givens(), board.givens());
+        assert!(restored.redo());
+        assert_eq!(restored.rows[8][0], 3);
+        assert!(restored.undo() && restored.undo());
+        assert_eq!(restored.cell_data[0][3].notes, 1 << 6);
+    }
+
+    #[test]
+    fn malformed_saved_games() {
+        let text = board_of(PUZZLE).save(0, 0).to_text();
+        let replaced = |key: &str, value: &str| -> String {
+            text.lines()
+                .map(|line| match line.split_once('=') {
+                    Some((line_key, _)) if line_key == key => format!("{}={}", key, value),
+                    _ => line.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+        let removed: String = text
+            .lines()
+            .filter(|line| !line.starts_with("notes="))
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        assert_eq!(
+            SavedGame::from_text(&removed),
+            Err(String::from("notes is missing"))
+        );
+        assert_eq!(
+            SavedGame::from_text(&replaced("values", &".".repeat(16))),
+            Err(String::from("givens and values are of different sizes"))
+        );
+        assert_eq!(
+            SavedGame::from_text(&replaced("notes", "0,0")),
+            Err(String::from("expected notes for each of 81 cells, found 2"))
+        );
+        assert_eq!(
+            SavedGame::from_text(&replaced("notes", &["1"; 81].join(","))),
+            Err(String::from("invalid notes '1'"))
+        );
+        assert_eq!(
+            SavedGame::from_text(&replaced("undo", "0,2,0,0,4")),
+            Err(String::from("invalid move '0,2,0,0,4'"))
+        );
+        assert_eq!(
+            SavedGame::from_text(&replaced("redo", "9,0,0,0,1,0")),
+            Err(String::from("invalid move '9,0,0,0,1,0'"))
+        );
+        assert_eq!(
+            SavedGame::from_text(&replaced("elapsed", "soon")),
+            Err(String::from("invalid elapsed time 'soon'"))
+        );
+        assert!(SavedGame::from_text(&replaced("givens", "53")).is_err());
+    }
+}
@@ -1,66 +1,396 @@
+#[allow(clippy::module_inception)]
 pub mod sudoku {
     use rand::prelude::*;
-    use std::boxed::Box;
-    use std::collections::HashSet;
-    use std::collections::LinkedList;
+    use rand::rngs::StdRng;
+    use std::collections::VecDeque;
+    use std::ops::{Index, IndexMut, RangeInclusive};
+    use std::slice::Chunks;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
     use std::thread;
+    use std::time::{Duration, Instant};
 
-    #[derive(Debug)]
-    struct Node {
-        row: u8,
-        col: u8,
-        board: [[u8; 9]; 9],
+    // Dimensions of a board. Board has size rows and size columns, values are 1 to size and
+    // boxes are box_rows high and box_cols wide.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct Shape {
+        pub size: u8,
+        pub box_rows: u8,
+        pub box_cols: u8,
     }
 
-    impl Node {
-        fn new(row: u8, col: u8, board: [[u8; 9]; 9]) -> Self {
-            Self { row, col, board }
+    impl Shape {
+        pub const CLASSIC: Shape = Shape {
+            size: 9,
+            box_rows: 3,
+            box_cols: 3,
+        };
+
+        // Supported sizes, i.e. sizes of_size returns a shape for
+        pub const SIZES: [u8; 5] = [4, 6, 9, 12, 16];
+
+        pub fn of_size(size: u8) -> Option<Self> {
+            let (box_rows, box_cols) = match size {
+                4 => (2, 2),
+                6 => (2, 3),
+                9 => (3, 3),
+                12 => (3, 4),
+                16 => (4, 4),
+                _ => return None,
+            };
+            Some(Self {
+                size,
+                box_rows,
+                box_cols,
+            })
+        }
+
+        pub fn cell_count(&self) -> usize {
+            self.size as usize * self.size as usize
+        }
+
+        // Character of a value, '.' for none. Values of 16x16 boards are hex digits 0 to F as in
+        // hexadoku, values of other boards are digits from 1 and letters from 'A' on for 10 and
+        // up.
+        pub fn value_char(&self, val: u8) -> char {
+            match val {
+                0 => '.',
+                _ if self.size == 16 => char::from(b"0123456789ABCDEF"[val as usize - 1]),
+                1..=9 => char::from(b'0' + val),
+                _ => char::from(b'A' + val - 10),
+            }
+        }
+
+        // Reverse of value_char for values up to size, letters are upper case
+        pub fn char_value(&self, c: char) -> Option<u8> {
+            let val = match c {
+                '0'..='9' | 'A'..='F' if self.size == 16 => c.to_digit(16)? as u8 + 1,
+                '1'..='9' => c as u8 - b'0',
+                'A'..='Z' if self.size != 16 => c as u8 - b'A' + 10,
+                _ => return None,
+            };
+            (val <= self.size).then_some(val)
+        }
+
+        // Rows are units 0 to size - 1, followed by columns and then boxes
+        pub fn unit_count(&self) -> usize {
+            3 * self.size as usize
+        }
+
+        // Boxes are counted row by row from top left
+        pub fn box_index(&self, row: u8, col: u8) -> u8 {
+            (row / self.box_rows) * (self.size / self.box_cols) + col / self.box_cols
+        }
+
+        // Bits of values 1 to size
+        pub fn all_values(&self) -> u32 {
+            ((1 << self.size) - 1) << 1
+        }
+
+        // Every unit is a rectangle of cells: a row, a column or a box
+        fn unit_cells(&self, unit: usize) -> impl Iterator<Item = (u8, u8)> {
+            let size = self.size;
+            let u = (unit % size as usize) as u8;
+            let (top, left, height, width) = match unit / size as usize {
+                0 => (u, 0, 1, size),
+                1 => (0, u, size, 1),
+                _ => {
+                    let boxes_per_row = size / self.box_cols;
+                    (
+                        (u / boxes_per_row) * self.box_rows,
+                        (u % boxes_per_row) * self.box_cols,
+                        self.box_rows,
+                        self.box_cols,
+                    )
+                }
+            };
+            (top..top + height).flat_map(move |row| (left..left + width).map(move |col| (row, col)))
+        }
+    }
+
+    // Killer cage, values of its cells are distinct and add up to sum
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct Cage {
+        // Sorted in row major order
+        pub cells: Vec<(u8, u8)>,
+        pub sum: u16,
+    }
+
+    // Extra rule of a variant, adding units to rows, columns and boxes. Every unit has size cells
+    // and each value once, so the engine treats them like the other units. A new rule of this
+    // kind only needs its units defined here.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub enum Constraint {
+        // Both main diagonals, as in Sudoku-X
+        Diagonals,
+    }
+
+    impl Constraint {
+        // Every constraint, in order of their names in puzzle text
+        pub const ALL: [Constraint; 1] = [Constraint::Diagonals];
+
+        // Short name used in puzzle text and on screen
+        pub fn name(&self) -> &'static str {
+            match self {
+                Constraint::Diagonals => "X",
+            }
+        }
+
+        pub fn unit_count(&self) -> usize {
+            match self {
+                Constraint::Diagonals => 2,
+            }
+        }
+
+        // Cells of unit, which is below unit_count
+        pub fn unit_cells(&self, shape: Shape, unit: usize) -> Vec<(u8, u8)> {
+            let last = shape.size - 1;
+            match self {
+                Constraint::Diagonals if unit == 0 => (0..=last).map(|i| (i, i)).collect(),
+                Constraint::Diagonals => (0..=last).map(|i| (i, last - i)).collect(),
+            }
+        }
+
+        pub fn contains(&self, shape: Shape, unit: usize, (row, col): (u8, u8)) -> bool {
+            match self {
+                Constraint::Diagonals if unit == 0 => row == col,
+                Constraint::Diagonals => row + col == shape.size - 1,
+            }
+        }
+    }
+
+    // Cells of a board in row major order. Indexing gives a row, so cells are accessed as
+    // grid[row][col] like with nested arrays.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct Grid<T = u8> {
+        shape: Shape,
+        // Cages of a killer board, none for classic boards
+        cages: Vec<Cage>,
+        constraints: Vec<Constraint>,
+        // Box of each cell in row major order for a jigsaw board, whose boxes are irregular
+        // regions. Empty if boxes are rectangles of the shape.
+        regions: Vec<u8>,
+        cells: Vec<T>,
+    }
+
+    impl<T: Copy + Default> Grid<T> {
+        // Grid of default values, i.e. an empty board
+        pub fn new(shape: Shape) -> Self {
+            Self {
+                shape,
+                cages: Vec::new(),
+                constraints: Vec::new(),
+                regions: Vec::new(),
+                cells: vec![T::default(); shape.cell_count()],
+            }
+        }
+
+        // Empty grid with the shape, cages, constraints and regions of other
+        pub fn with_rules_of<U>(other: &Grid<U>) -> Self {
+            Self {
+                shape: other.shape,
+                cages: other.cages.clone(),
+                constraints: other.constraints.clone(),
+                regions: other.regions.clone(),
+                cells: vec![T::default(); other.shape.cell_count()],
+            }
+        }
+
+        // Box of each cell in row major order if boxes are irregular regions
+        pub fn regions(&self) -> Option<&[u8]> {
+            (!self.regions.is_empty()).then_some(self.regions.as_slice())
+        }
+
+        // Each of the size regions is expected to have size cells
+        pub fn set_regions(&mut self, regions: Vec<u8>) {
+            self.regions = regions;
+        }
+
+        // Boxes are counted row by row from top left, regions keep their own indexes
+        pub fn box_index(&self, row: u8, col: u8) -> u8 {
+            if self.regions.is_empty() {
+                self.shape.box_index(row, col)
+            } else {
+                self.regions[row as usize * self.shape.size as usize + col as usize]
+            }
+        }
+
+        pub fn constraints(&self) -> &[Constraint] {
+            &self.constraints
+        }
+
+        pub fn set_constraints(&mut self, constraints: Vec<Constraint>) {
+            self.constraints = constraints;
+        }
+
+        // Units of rows, columns and boxes followed by those of constraints
+        pub fn unit_count(&self) -> usize {
+            self.shape.unit_count() + self.extra_unit_count()
+        }
+
+        pub fn unit_cells(&self, unit: usize) -> impl Iterator<Item = (u8, u8)> + '_ {
+            let shape_units = self.shape.unit_count();
+            let size = self.shape.size as usize;
+            let is_region = !self.regions.is_empty() && (2 * size..shape_units).contains(&unit);
+            let region_cells = is_region.then(|| {
+                let region = (unit - 2 * size) as u8;
+                self.regions
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, cell_region)| **cell_region == region)
+                    .map(move |(index, _)| ((index / size) as u8, (index % size) as u8))
+            });
+            let extra_cells =
+                (unit >= shape_units).then(|| self.extra_unit_cells(unit - shape_units));
+            (unit < shape_units && !is_region)
+                .then(|| self.shape.unit_cells(unit))
+                .into_iter()
+                .flatten()
+                .chain(region_cells.into_iter().flatten())
+                .chain(extra_cells.into_iter().flatten())
+        }
+
+        // Number of units added by constraints
+        pub fn extra_unit_count(&self) -> usize {
+            self.constraints.iter().map(|c| c.unit_count()).sum()
+        }
+
+        // Cells of a unit added by constraints, counted from 0
+        pub fn extra_unit_cells(&self, unit: usize) -> Vec<(u8, u8)> {
+            let mut unit = unit;
+            for constraint in self.constraints.iter() {
+                if unit < constraint.unit_count() {
+                    return constraint.unit_cells(self.shape, unit);
+                }
+                unit -= constraint.unit_count();
+            }
+            Vec::new()
+        }
+
+        // Units added by constraints which have the cell
+        pub fn extra_units_of(&self, row: u8, col: u8) -> Vec<usize> {
+            let mut units: Vec<usize> = Vec::new();
+            let mut first_unit = 0;
+            for constraint in self.constraints.iter() {
+                for unit in 0..constraint.unit_count() {
+                    if constraint.contains(self.shape, unit, (row, col)) {
+                        units.push(first_unit + unit);
+                    }
+                }
+                first_unit += constraint.unit_count();
+            }
+            units
+        }
+
+        pub fn cages(&self) -> &[Cage] {
+            &self.cages
+        }
+
+        pub fn set_cages(&mut self, cages: Vec<Cage>) {
+            self.cages = cages;
+        }
+
+        pub fn cage_at(&self, row: u8, col: u8) -> Option<&Cage> {
+            self.cages
+                .iter()
+                .find(|cage| cage.cells.contains(&(row, col)))
+        }
+
+        // Index of the cage of each cell
+        pub fn cage_map(&self) -> Grid<Option<usize>> {
+            let mut cage_map = Grid::new(self.shape);
+            for (index, cage) in self.cages.iter().enumerate() {
+                for &(row, col) in cage.cells.iter() {
+                    cage_map[row as usize][col as usize] = Some(index);
+                }
+            }
+            cage_map
+        }
+
+        pub fn shape(&self) -> Shape {
+            self.shape
+        }
+
+        pub fn rows(&self) -> Chunks<'_, T> {
+            self.cells.chunks(self.shape.size as usize)
+        }
+
+        // All cells in row major order
+        pub fn cells(&self) -> &[T] {
+            &self.cells
         }
     }
 
-    impl Clone for Node {
-        fn clone(&self) -> Self {
-            let row = self.row;
-            let col = self.col;
-            let board = self.board.clone();
-            Self { row, col, board }
+    impl<T> Index<usize> for Grid<T> {
+        type Output = [T];
+
+        fn index(&self, row: usize) -> &[T] {
+            let size = self.shape.size as usize;
+            &self.cells[row * size..(row + 1) * size]
+        }
+    }
+
+    impl<T> IndexMut<usize> for Grid<T> {
+        fn index_mut(&mut self, row: usize) -> &mut [T] {
+            let size = self.shape.size as usize;
+            &mut self.cells[row * size..(row + 1) * size]
         }
     }
 
-    // TODO: write unit test
-    pub fn available_values(board: &[[u8; 9]; 9], row: u8, col: u8) -> Vec<u8> {
+    pub fn available_values(board: &Grid, row: u8, col: u8) -> Vec<u8> {
         let val = board[row as usize][col as usize];
         // Return current value in case it's non zero
         if val > 0 {
             return Vec::from([val]);
         }
 
-        // Note that value zero means empty
-        let mut values = HashSet::from([1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        // Note that value zero means empty. Values are kept in order, so that
+        // search and generation are deterministic.
+        let shape = board.shape();
+        let mut used = [false; 17];
 
         // Check same row
-        for i in 0..9 {
-            values.remove(&board[row as usize][i]);
+        for val in board[row as usize].iter() {
+            used[*val as usize] = true;
         }
         // Check same column
-        for i in 0..9 {
-            values.remove(&board[i][col as usize]);
+        for board_row in board.rows() {
+            used[board_row[col as usize] as usize] = true;
         }
         // Check same rect
-        for r in ((row / 3) * 3)..((row / 3) * 3 + 3) {
-            for c in ((col / 3) * 3)..((col / 3) * 3 + 3) {
-                values.remove(&board[r as usize][c as usize]);
+        let rect_unit = 2 * shape.size as usize + board.box_index(row, col) as usize;
+        for (r, c) in board.unit_cells(rect_unit) {
+            used[board[r as usize][c as usize] as usize] = true;
+        }
+        // Check same cage
+        if let Some(cage) = board.cage_at(row, col) {
+            for (r, c) in cage.cells.iter() {
+                used[board[*r as usize][*c as usize] as usize] = true;
+            }
+        }
+        // Check units of constraints
+        for unit in board.extra_units_of(row, col) {
+            for (r, c) in board.extra_unit_cells(unit) {
+                used[board[r as usize][c as usize] as usize] = true;
             }
         }
 
-        values.into_iter().collect()
+        (1..=shape.size)
+            .filter(|val| !used[*val as usize])
+            .collect()
     }
 
     // Checks whether index 1 and index 2 are either;
     // in same row
     // in same col
     // in same rect
-    pub fn are_related(index1: (u8, u8), index2: (u8, u8)) -> bool {
+    // in same unit of a constraint of grid
+    pub fn are_related<T: Copy + Default>(
+        grid: &Grid<T>,
+        index1: (u8, u8),
+        index2: (u8, u8),
+    ) -> bool {
+        let shape = grid.shape;
         // Check same row
         if index1.0 == index2.0 {
             return true;
@@ -70,23 +400,34 @@ pub mod sudoku {
             return true;
         }
         // Check same rect
-        if index1.0 / 3 == index2.0 / 3 && index1.1 / 3 == index2.1 / 3 {
+        if grid.box_index(index1.0, index1.1) == grid.box_index(index2.0, index2.1) {
             return true;
         }
+        // Check same unit of a constraint
+        for constraint in grid.constraints.iter() {
+            for unit in 0..constraint.unit_count() {
+                if constraint.contains(shape, unit, index1)
+                    && constraint.contains(shape, unit, index2)
+                {
+                    return true;
+                }
+            }
+        }
 
         false
     }
 
-    // TODO: write unit test
-    pub fn is_valid(board: &[[u8; 9]; 9], row: u8, col: u8) -> bool {
+    pub fn is_valid(board: &Grid, row: u8, col: u8) -> bool {
         let value = board[row as usize][col as usize];
 
         if value == 0 {
             return true;
         }
 
+        let shape = board.shape();
+
         // Check same col
-        for row_i in 0..9 {
+        for row_i in 0..shape.size {
             if row_i == row {
                 continue;
             }
@@ -96,7 +437,7 @@ pub mod sudoku {
         }
 
         // Check same row
-        for col_i in 0..9 {
+        for col_i in 0..shape.size {
             if col_i == col {
                 continue;
             }
@@ -106,24 +447,52 @@ pub mod sudoku {
         }
 
         // Check same rect
-        for row_i in ((row / 3) * 3)..((row / 3) * 3 + 3) {
-            for col_i in ((col / 3) * 3)..((col / 3) * 3 + 3) {
-                if row_i == row && col_i == col {
-                    continue;
+        let rect_unit = 2 * shape.size as usize + board.box_index(row, col) as usize;
+        for (row_i, col_i) in board.unit_cells(rect_unit) {
+            if row_i == row && col_i == col {
+                continue;
+            }
+            if board[row_i as usize][col_i as usize] == value {
+                return false;
+            }
+        }
+
+        // Check units of constraints
+        for unit in board.extra_units_of(row, col) {
+            for (row_i, col_i) in board.extra_unit_cells(unit) {
+                if (row_i, col_i) != (row, col) && board[row_i as usize][col_i as usize] == value {
+                    return false;
                 }
-                if board[row_i as usize][col_i as usize] == value {
+            }
+        }
+
+        // Check same cage, its sum can't be exceeded since every empty cell needs at least 1
+        if let Some(cage) = board.cage_at(row, col) {
+            let mut sum: u16 = 0;
+            let mut empty_cells: u16 = 0;
+            for &(row_i, col_i) in cage.cells.iter() {
+                let cage_value = board[row_i as usize][col_i as usize];
+                if (row_i, col_i) != (row, col) && cage_value == value {
                     return false;
                 }
+                if cage_value == 0 {
+                    empty_cells += 1;
+                } else {
+                    sum += cage_value as u16;
+                }
+            }
+            if sum + empty_cells > cage.sum || (empty_cells == 0 && sum != cage.sum) {
+                return false;
             }
         }
 
         true
     }
 
-    // TODO: write unit test
-    pub fn is_finished(board: &[[u8; 9]; 9]) -> bool {
-        for row in 0..9 {
-            for col in 0..9 {
+    pub fn is_finished(board: &Grid) -> bool {
+        let size = board.shape().size;
+        for row in 0..size {
+            for col in 0..size {
                 if board[row as usize][col as usize] == 0 {
                     return false;
                 }
@@ -136,208 +505,3095 @@ pub mod sudoku {
         true
     }
 
-    // Performs depth first search on node
-    // @return solutions
-    fn search(node: &mut Box<Node>) -> Vec<[[u8; 9]; 9]> {
-        let mut all_nodes: Vec<[[u8; 9]; 9]> = Vec::new();
+    // Board keeping values used in each row, column and box as bit masks, bit n is set when
+    // value n is used. Candidates of a cell are found with a few bit operations instead of
+    // scanning its row, column and box. Board is expected to have no conflicts.
+    #[derive(Clone, Debug)]
+    pub struct BitBoard {
+        cells: Grid,
+        rows: [u32; 16],
+        cols: [u32; 16],
+        boxes: [u32; 16],
+        // Values used in each unit of constraints, then those units of each cell in row major
+        // order
+        extra_units: Vec<u32>,
+        extra_units_of: Vec<Vec<usize>>,
+        // Cage of each cell, then values used in each cage, their sum and empty cell count
+        cage_map: Grid<Option<usize>>,
+        cage_values: Vec<u32>,
+        cage_sums: Vec<u16>,
+        cage_empty_cells: Vec<u8>,
+    }
+
+    impl BitBoard {
+        pub fn new(board: &Grid) -> Self {
+            let shape = board.shape();
+            let extra_units_of = (0..shape.cell_count())
+                .map(|index| {
+                    let size = shape.size as usize;
+                    board.extra_units_of((index / size) as u8, (index % size) as u8)
+                })
+                .collect();
+            let mut bit_board = Self {
+                cells: Grid::with_rules_of(board),
+                rows: [0; 16],
+                cols: [0; 16],
+                boxes: [0; 16],
+                extra_units: vec![0; board.extra_unit_count()],
+                extra_units_of,
+                cage_map: board.cage_map(),
+                cage_values: vec![0; board.cages().len()],
+                cage_sums: vec![0; board.cages().len()],
+                cage_empty_cells: board
+                    .cages()
+                    .iter()
+                    .map(|cage| cage.cells.len() as u8)
+                    .collect(),
+            };
+            for row in 0..shape.size {
+                for col in 0..shape.size {
+                    let val = board[row as usize][col as usize];
+                    if val != 0 {
+                        bit_board.set(row, col, val);
+                    }
+                }
+            }
 
-        // Use linked list as stack because we'll be worknig with last element all the time
-        let mut nodes_stack: LinkedList<Box<Node>> = LinkedList::new();
-        for child_node in create_children(node) {
-            nodes_stack.push_back(child_node);
+            bit_board
         }
 
-        while !nodes_stack.is_empty() {
-            let node = nodes_stack.pop_back().unwrap();
+        pub fn board(&self) -> Grid {
+            self.cells.clone()
+        }
 
-            if node.row == 8 {
-                if node.col == 8 {
-                    all_nodes.push(node.board);
-                    continue;
-                }
-            }
+        pub fn get(&self, row: u8, col: u8) -> u8 {
+            self.cells[row as usize][col as usize]
+        }
 
-            for child_node in create_children(&node) {
-                nodes_stack.push_back(child_node);
+        // Values which can be placed to cell as bit mask, none for filled cells
+        pub fn candidates(&self, row: u8, col: u8) -> u32 {
+            if self.get(row, col) != 0 {
+                return 0;
+            }
+            let used = self.extra_units_of[self.cell_index(row, col)].iter().fold(
+                self.rows[row as usize]
+                    | self.cols[col as usize]
+                    | self.boxes[self.box_index(row, col)],
+                |used, unit| used | self.extra_units[*unit],
+            );
+            let candidates = !used & self.cells.shape().all_values();
+            match self.cage_map[row as usize][col as usize] {
+                Some(cage) => self.cage_candidates(cage, candidates),
+                None => candidates,
             }
         }
 
-        all_nodes
-    }
+        // Candidates which are unused in the cage and still let it add up to its sum with
+        // distinct values in the other empty cells
+        fn cage_candidates(&self, cage: usize, candidates: u32) -> u32 {
+            let candidates = candidates & !self.cage_values[cage];
+            // Sum is exceeded already if board has conflicts
+            let Some(remaining) = self.cells.cages()[cage]
+                .sum
+                .checked_sub(self.cage_sums[cage])
+            else {
+                return 0;
+            };
+            let remaining = remaining as u32;
+            let other_cells = self.cage_empty_cells[cage] as u32 - 1;
+            let unused = self.cells.shape().all_values() & !self.cage_values[cage];
+
+            let mut cage_candidates = 0;
+            for val in mask_values(candidates) {
+                let rest = match remaining.checked_sub(val as u32) {
+                    Some(rest) => rest,
+                    None => break,
+                };
+                if let Some((min, max)) = sum_bounds(unused & !(1 << val), other_cells) {
+                    if (min..=max).contains(&rest) {
+                        cage_candidates |= 1 << val;
+                    }
+                }
+            }
+            cage_candidates
+        }
 
-    fn create_children(node: &Box<Node>) -> Vec<Box<Node>> {
-        let child_row;
-        let child_col;
+        // Places value to an empty cell
+        pub fn set(&mut self, row: u8, col: u8, val: u8) {
+            self.cells[row as usize][col as usize] = val;
+            self.rows[row as usize] |= 1 << val;
+            self.cols[col as usize] |= 1 << val;
+            self.boxes[self.box_index(row, col)] |= 1 << val;
+            for unit in self.extra_units_of[self.cell_index(row, col)].iter() {
+                self.extra_units[*unit] |= 1 << val;
+            }
+            if let Some(cage) = self.cage_map[row as usize][col as usize] {
+                self.cage_values[cage] |= 1 << val;
+                self.cage_sums[cage] += val as u16;
+                self.cage_empty_cells[cage] -= 1;
+            }
+        }
 
-        if node.col == 8 {
-            if node.row == 8 {
-                return Vec::new(); // We reached end of the board
+        pub fn clear(&mut self, row: u8, col: u8) {
+            let val = self.get(row, col);
+            self.cells[row as usize][col as usize] = 0;
+            self.rows[row as usize] &= !(1 << val);
+            self.cols[col as usize] &= !(1 << val);
+            self.boxes[self.box_index(row, col)] &= !(1 << val);
+            for unit in self.extra_units_of[self.cell_index(row, col)].iter() {
+                self.extra_units[*unit] &= !(1 << val);
+            }
+            if let Some(cage) = self.cage_map[row as usize][col as usize] {
+                self.cage_values[cage] &= !(1 << val);
+                self.cage_sums[cage] -= val as u16;
+                self.cage_empty_cells[cage] += 1;
             }
-            child_row = node.row + 1;
-            child_col = 0;
-        } else {
-            child_row = node.row;
-            child_col = node.col + 1;
         }
 
-        let mut all_nodes: Vec<Box<Node>> = Vec::new();
+        fn box_index(&self, row: u8, col: u8) -> usize {
+            self.cells.box_index(row, col) as usize
+        }
 
-        for val in available_values(&node.board, child_row, child_col) {
-            let mut child_board = node.board.clone();
-            child_board[child_row as usize][child_col as usize] = val;
-            all_nodes.push(Box::new(Node::new(child_row, child_col, child_board)));
+        fn cell_index(&self, row: u8, col: u8) -> usize {
+            row as usize * self.cells.shape().size as usize + col as usize
         }
+    }
 
-        all_nodes
+    // Smallest and largest sum of count distinct values out of mask, None if mask has fewer
+    fn sum_bounds(mask: u32, count: u32) -> Option<(u32, u32)> {
+        if mask.count_ones() < count {
+            return None;
+        }
+        let (mut low, mut high) = (mask, mask);
+        let (mut min, mut max) = (0, 0);
+        for _ in 0..count {
+            min += low.trailing_zeros();
+            low &= low - 1;
+            let top = 31 - high.leading_zeros();
+            max += top;
+            high &= !(1 << top);
+        }
+        Some((min, max))
     }
 
-    fn all_solutions(board: &[[u8; 9]; 9]) -> Vec<[[u8; 9]; 9]> {
-        let mut solutions: Vec<[[u8; 9]; 9]> = Vec::new();
+    // Performs depth first search until limit solutions are found. Cells having a single
+    // candidate are filled before branching, then the cell with fewest candidates is branched on.
+    fn search(board: &mut BitBoard, limit: usize, solutions: &mut Vec<Grid>) {
+        let size = board.cells.shape().size;
+        // Cells filled by propagation, to be cleared before returning
+        let mut filled: Vec<(u8, u8)> = Vec::new();
+        let mut branch_cell: Option<(u8, u8, u32)> = None;
+
+        let mut propagate = true;
+        while propagate {
+            propagate = false;
+            branch_cell = None;
+            for row in 0..size {
+                for col in 0..size {
+                    if board.get(row, col) != 0 {
+                        continue;
+                    }
+                    let candidates = board.candidates(row, col);
+                    match candidates.count_ones() {
+                        0 => {
+                            // Dead end
+                            for (row, col) in filled.into_iter().rev() {
+                                board.clear(row, col);
+                            }
+                            return;
+                        }
+                        1 => {
+                            board.set(row, col, candidates.trailing_zeros() as u8);
+                            filled.push((row, col));
+                            propagate = true;
+                        }
+                        count => {
+                            if branch_cell.is_none_or(|(_, _, best)| count < best.count_ones()) {
+                                branch_cell = Some((row, col, candidates));
+                            }
+                        }
+                    }
+                }
+            }
+        }
 
-        // Create initial nodes. Ideally it'll be one for each thread but
-        // it can be a little more, depending on available values for a cell
-        let ideal_thread_count = thread::available_parallelism().unwrap().get();
+        match branch_cell {
+            None => solutions.push(board.board()),
+            Some((row, col, mut candidates)) => {
+                while candidates != 0 {
+                    // Take lowest value first, so that search is deterministic
+                    let val = candidates.trailing_zeros() as u8;
+                    candidates &= candidates - 1;
 
-        let mut head_nodes: Vec<Box<Node>> = Vec::new();
+                    board.set(row, col, val);
+                    search(board, limit, solutions);
+                    board.clear(row, col);
+                    if solutions.len() >= limit {
+                        break;
+                    }
+                }
+            }
+        }
 
-        for val in available_values(&board, 0, 0) {
-            let mut copy_board = board.clone();
-            copy_board[0][0] = val;
-            head_nodes.push(Box::new(Node::new(0, 0, copy_board)));
+        for (row, col) in filled.into_iter().rev() {
+            board.clear(row, col);
         }
+    }
 
-        let mut c = 0;
-        while head_nodes.len() < ideal_thread_count {
-            if c == 30 {
-                // If it takes too long to reach, just break
-                break;
-            }
-            let node;
-            match head_nodes.pop() {
-                Some(_node) => node = _node,
+    // Parses a board from size * size characters, size being one of Shape::SIZES. Values are
+    // characters of Shape::value_char in upper or lower case, and '.' is an empty cell. So is '0'
+    // unless board is 16x16, where it is a value. Whitespace is ignored,
+    // so a grid of lines is accepted as well. Sections separated by '|' may follow, each being
+    // the name of a constraint, the regions of a jigsaw board (see parse_regions) or the cages
+    // of a killer board (see parse_cages).
+    pub fn parse_board(text: &str) -> Result<Grid, String> {
+        let mut sections = text.split('|');
+        let text = sections.next().unwrap_or_default();
+        let chars: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let Some(shape) = Shape::SIZES
+            .iter()
+            .filter_map(|size| Shape::of_size(*size))
+            .find(|shape| shape.cell_count() == chars.len())
+        else {
+            return Err(format!(
+                "expected 16, 36, 81, 144 or 256 cells, found {}",
+                chars.len()
+            ));
+        };
+
+        let size = shape.size as usize;
+        let mut board = Grid::new(shape);
+        for (i, c) in chars.iter().enumerate() {
+            let val = match c {
+                '.' => Some(0),
+                '0' if shape.size != 16 => Some(0),
+                _ => shape.char_value(c.to_ascii_uppercase()),
+            };
+            board[i / size][i % size] = match val {
+                Some(val) => val,
                 None => {
-                    break;
+                    return Err(format!(
+                        "invalid character '{}' at row {}, col {}",
+                        c,
+                        i / size + 1,
+                        i % size + 1
+                    ))
                 }
+            };
+        }
+
+        let mut constraints: Vec<Constraint> = Vec::new();
+        for section in sections {
+            let name = section.trim();
+            if name.is_empty() {
+                return Err(String::from("empty section after '|'"));
             }
-            if node.row == 8 && node.col == 8 {
-                solutions.push(node.board.clone());
+            match Constraint::ALL
+                .iter()
+                .find(|constraint| constraint.name().eq_ignore_ascii_case(name))
+            {
+                Some(constraint) if !constraints.contains(constraint) => {
+                    constraints.push(*constraint)
+                }
+                Some(_) => return Err(format!("constraint {} is given twice", name)),
+                None if name.chars().all(|c| c.is_ascii_alphabetic()) => {
+                    if board.regions().is_some() {
+                        return Err(String::from("regions are given twice"));
+                    }
+                    board.set_regions(parse_regions(name, shape)?);
+                }
+                None if board.cages().is_empty() => board.set_cages(parse_cages(section, shape)?),
+                None => return Err(String::from("cages are given twice")),
             }
-            let mut child_nodes = create_children(&node);
-            head_nodes.append(&mut child_nodes);
-            c = c + 1;
         }
+        board.set_constraints(constraints);
+
+        Ok(board)
+    }
 
-        let mut join_handles: Vec<thread::JoinHandle<Vec<[[u8; 9]; 9]>>> = Vec::new();
-        while !head_nodes.is_empty() {
-            let mut node = head_nodes.pop().unwrap();
-            join_handles.push(thread::spawn(move || search(&mut node)));
+    // Parses regions of a jigsaw board given as a letter for each cell, cells of the first region
+    // being 'A'. Each of the size regions must have size connected cells.
+    pub fn parse_regions(text: &str, shape: Shape) -> Result<Vec<u8>, String> {
+        if text.len() != shape.cell_count() {
+            return Err(format!(
+                "expected a region letter for each of {} cells, found {}",
+                shape.cell_count(),
+                text.len()
+            ));
+        }
+        let regions: Vec<u8> = text
+            .chars()
+            .map(|c| c.to_ascii_uppercase() as u8 - b'A')
+            .collect();
+        for region in 0..shape.size {
+            let cell_count = regions.iter().filter(|r| **r == region).count();
+            if cell_count != shape.size as usize {
+                return Err(format!(
+                    "region {} has {} cells, expected {}",
+                    char::from(b'A' + region),
+                    cell_count,
+                    shape.size
+                ));
+            }
+            if !is_connected(shape, &regions, region) {
+                return Err(format!(
+                    "region {} is not connected",
+                    char::from(b'A' + region)
+                ));
+            }
         }
 
-        for handle in join_handles {
-            match handle.join() {
-                Ok(boards) => {
-                    let mut mut_boards = boards.clone();
-                    solutions.append(&mut mut_boards);
+        Ok(regions)
+    }
+
+    // Parses cages separated by ';', each written as its sum followed by ':' and its cells as
+    // comma separated row major indexes, e.g. "10:0,1;7:2,11"
+    pub fn parse_cages(text: &str, shape: Shape) -> Result<Vec<Cage>, String> {
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut cages: Vec<Cage> = Vec::new();
+        let mut caged = vec![false; shape.cell_count()];
+        for cage_text in text.split(';').filter(|cage_text| !cage_text.is_empty()) {
+            let invalid_cage = || format!("invalid cage '{}'", cage_text);
+            let (sum, cells) = cage_text.split_once(':').ok_or_else(invalid_cage)?;
+            let sum: u16 = sum.parse().map_err(|_| invalid_cage())?;
+
+            let mut cage = Cage {
+                cells: Vec::new(),
+                sum,
+            };
+            for cell in cells.split(',') {
+                let index: usize = cell.parse().map_err(|_| invalid_cage())?;
+                if index >= shape.cell_count() {
+                    return Err(format!(
+                        "cell {} of cage '{}' is out of board",
+                        index, cage_text
+                    ));
                 }
-                Err(_) => {
-                    eprintln!("Thread failed")
+                if caged[index] {
+                    return Err(format!("cell {} is in more than one cage", index));
                 }
+                caged[index] = true;
+                let size = shape.size as usize;
+                cage.cells
+                    .push(((index / size) as u8, (index % size) as u8));
             }
+            cage.cells.sort();
+            cages.push(cage);
         }
 
-        solutions
+        Ok(cages)
     }
 
-    fn adjust_difficulty(solved_board: &[[u8; 9]; 9], difficulty: u8) -> (u8, [[u8; 9]; 9]) {
-        let mut board = solved_board.clone();
-        let mut current_difficulty: u8 = 0; // 0: easiest, 255: hardest
+    // Formats board as size * size characters, '.' being empty cells
+    pub fn format_board(board: &Grid) -> String {
+        let shape = board.shape();
+        board
+            .cells()
+            .iter()
+            .map(|val| shape.value_char(*val))
+            .collect()
+    }
 
-        // Remove random cell and verify board has still one solution until desired difficulty is reached
-        let mut rng = rand::rng();
-        let mut all_indexes: Vec<u8> = (0..81).collect();
-        let mut current_index = 0;
-        all_indexes.shuffle(&mut rng);
-        while current_difficulty < difficulty {
-            if current_index >= all_indexes.len() {
-                break;
-            }
-            let row: u8 = all_indexes[current_index] / 9;
-            let col: u8 = all_indexes[current_index] % 9;
+    // Formats board as format_board does, followed by its constraints, regions and cages if it
+    // has any
+    pub fn format_puzzle(board: &Grid) -> String {
+        let mut text = format_board(board);
+        for constraint in board.constraints() {
+            text.push('|');
+            text.push_str(constraint.name());
+        }
+        if let Some(regions) = board.regions() {
+            text.push('|');
+            text.extend(regions.iter().map(|region| char::from(b'A' + region)));
+        }
+        if board.cages().is_empty() {
+            return text;
+        }
 
-            let val: u8 = board[row as usize][col as usize];
-            board[row as usize][col as usize] = 0; // Remove data from cell
-            let solutions = all_solutions(&board);
-            if solutions.len() > 1 {
-                // Revert removal
-                board[row as usize][col as usize] = val;
-            } else {
-                // Since we're working on solved board, solution count should be at least one.
-                // Else case assumes solution count is one.
-                current_difficulty = current_difficulty + 3;
+        let size = board.shape().size as usize;
+        let cages: Vec<String> = board
+            .cages()
+            .iter()
+            .map(|cage| {
+                let cells: Vec<String> = cage
+                    .cells
+                    .iter()
+                    .map(|(row, col)| (*row as usize * size + *col as usize).to_string())
+                    .collect();
+                format!("{}:{}", cage.sum, cells.join(","))
+            })
+            .collect();
+        text.push('|');
+        text.push_str(&cages.join(";"));
+        text
+    }
+
+    // Checks whether board is playable, i.e. has no conflicts and has exactly one solution
+    pub fn validate_puzzle(board: &Grid) -> Result<(), String> {
+        let size = board.shape().size;
+        for row in 0..size {
+            for col in 0..size {
+                if !is_valid(board, row, col) {
+                    return Err(format!(
+                        "value {} at row {}, col {} conflicts with another cell",
+                        board.shape().value_char(board[row as usize][col as usize]),
+                        row + 1,
+                        col + 1
+                    ));
+                }
             }
-            current_index = current_index + 1;
         }
 
-        (current_difficulty, board)
+        match count_solutions(board, 2) {
+            0 => Err(String::from("puzzle has no solution")),
+            1 => Ok(()),
+            _ => Err(String::from("puzzle has more than one solution")),
+        }
     }
 
-    // TODO: write unit test
-    // difficulty is in between 0-255
-    pub fn generate_initial_board(difficulty: u8) -> [[u8; 9]; 9] {
-        let mut solutions: Vec<[[u8; 9]; 9]> = Vec::new();
-
-        let mut try_count = 1;
-        let mut rng = rand::rng();
-        while solutions.is_empty() {
-            // Value 0 (zero) means cell is empty
-            let mut board: [[u8; 9]; 9] = [[0; 9]; 9];
+    // Checks whether board has exactly one solution and removing any of its values makes it
+    // have more, i.e. every value is needed
+    pub fn is_minimal(board: &Grid) -> bool {
+        if count_solutions(board, 2) != 1 {
+            return false;
+        }
 
-            // Assign random but valid initial values
-            let mut all_indexes = [0; 81];
-            for i in 1..81 {
-                all_indexes[i] = i;
-            }
-            all_indexes.shuffle(&mut rng);
-            // 30 is magic number, an optimized value
-            for i in 0..30 {
-                let row = all_indexes[i] / 9;
-                let col = all_indexes[i] % 9;
-                let available_values = available_values(&board, row as u8, col as u8);
-                if available_values.is_empty() {
+        let mut board = board.clone();
+        let size = board.shape().size as usize;
+        for row in 0..size {
+            for col in 0..size {
+                let val = board[row][col];
+                if val == 0 {
                     continue;
                 }
-                let index = rng.random::<u8>() % available_values.len() as u8;
-                board[row][col] = available_values[index as usize];
+                board[row][col] = 0;
+                let needed = count_solutions(&board, 2) > 1;
+                board[row][col] = val;
+                if !needed {
+                    return false;
+                }
             }
+        }
+
+        true
+    }
+
+    // Counts solutions of the board, stopping as soon as limit solutions are found. Board is
+    // searched right away, since it is usually rated after as well, which solves it logically.
+    pub fn count_solutions(board: &Grid, limit: usize) -> usize {
+        if has_conflicts(board) {
+            return 0;
+        }
+        solver_of(board).solutions(board, limit).len()
+    }
+
+    // Solutions of the board up to limit, none if initial values conflict
+    pub fn find_solutions(board: &Grid, limit: usize) -> Vec<Grid> {
+        if has_conflicts(board) {
+            return Vec::new();
+        }
+
+        // Logical steps are forced, so no need to search when board is solved logically
+        let (_, solved_board) = solve_logically(board);
+        if is_finished(&solved_board) {
+            return vec![solved_board];
+        }
+
+        solver_of(board).solutions(board, limit)
+    }
+
+    // Boards with cages are searched with dancing links, which fills whole cages at once and gets
+    // lost much less. So are boards larger than 9x9, since it also places values which fit in a
+    // single cell of a unit, which depth first search on large boards takes very long to find.
+    pub fn solver_of(board: &Grid) -> &'static dyn Solver {
+        if board.cages().is_empty() && board.shape().size <= 9 {
+            &DfsSolver
+        } else {
+            &DlxSolver
+        }
+    }
+
+    fn has_conflicts(board: &Grid) -> bool {
+        let size = board.shape().size;
+        (0..size).any(|row| (0..size).any(|col| !is_valid(board, row, col)))
+    }
+
+    // Same as find_solutions, searching with given solver even when board is solved logically
+    pub fn find_solutions_with(solver: &dyn Solver, board: &Grid, limit: usize) -> Vec<Grid> {
+        if has_conflicts(board) {
+            return Vec::new();
+        }
+        solver.solutions(board, limit)
+    }
+
+    // Backend searching solutions of a board
+    pub trait Solver: Sync {
+        // Solutions of the board up to limit, board is expected to have no conflicts
+        fn solutions(&self, board: &Grid, limit: usize) -> Vec<Grid>;
+    }
+
+    // Depth first search on BitBoard
+    pub struct DfsSolver;
 
-            solutions = all_solutions(&board);
-            try_count = try_count + 1;
+    impl Solver for DfsSolver {
+        fn solutions(&self, board: &Grid, limit: usize) -> Vec<Grid> {
+            let mut solutions: Vec<Grid> = Vec::new();
+            search(&mut BitBoard::new(board), limit, &mut solutions);
+            solutions
         }
+    }
+
+    // Exact cover search with dancing links. Each choice of a value for a cell is a row, which
+    // covers 4 constraints: the cell is filled, and the value is used once in its row, column
+    // and box. Units of constraints add a column for each of their values. Cells of a killer
+    // cage are filled together, each way to fill the cage is a row.
+    pub struct DlxSolver;
 
-        let mut join_handles: Vec<thread::JoinHandle<(u8, [[u8; 9]; 9])>> = Vec::new();
-        for solved_board in solutions {
-            join_handles.push(thread::spawn(move || {
-                adjust_difficulty(&solved_board, difficulty)
-            }));
+    impl Solver for DlxSolver {
+        fn solutions(&self, board: &Grid, limit: usize) -> Vec<Grid> {
+            self.solutions_until(board, limit, None, &CancelToken::new())
         }
+    }
+
+    impl DlxSolver {
+        // Same as solutions, but gives up at the deadline or once cancelled and returns solutions
+        // found until then
+        pub fn solutions_until(
+            &self,
+            board: &Grid,
+            limit: usize,
+            deadline: Option<Instant>,
+            cancel: &CancelToken,
+        ) -> Vec<Grid> {
+            let shape = board.shape();
+            let size = shape.size as usize;
+            let cell_count = shape.cell_count();
 
-        let mut game_board: [[u8; 9]; 9] = [[0; 9]; 9];
-        let mut best_match_score = 255;
-        for handle in join_handles {
-            match handle.join() {
-                Ok((difficulty_score, board)) => {
-                    let score = difficulty_score.abs_diff(difficulty);
-                    if score < best_match_score {
-                        best_match_score = score;
-                        game_board = board.clone();
+            // Values placed by each row as (row, col, val)
+            let mut choices: Vec<Vec<(u8, u8, u8)>> = Vec::new();
+            let cage_map = board.cage_map();
+            for (row, board_row) in board.rows().enumerate() {
+                for (col, given) in board_row.iter().enumerate() {
+                    if cage_map[row][col].is_some() {
+                        continue;
+                    }
+                    for val in 1..=shape.size {
+                        // Only the given value is an option for filled cells
+                        if *given != 0 && *given != val {
+                            continue;
+                        }
+                        choices.push(vec![(row as u8, col as u8, val)]);
                     }
                 }
-                Err(_) => {
-                    eprintln!("Thread failed");
+            }
+            for cage in board.cages() {
+                fill_cage(board, cage, 0, &mut Vec::new(), &mut choices);
+            }
+
+            let mut links = DancingLinks::new(4 * cell_count + board.extra_unit_count() * size);
+            for (id, choice) in choices.iter().enumerate() {
+                let columns: Vec<usize> = choice
+                    .iter()
+                    .flat_map(|&(row, col, val)| {
+                        let rect = board.box_index(row, col) as usize;
+                        let extra_units = board.extra_units_of(row, col);
+                        let (row, col, val) = (row as usize, col as usize, val as usize);
+                        [
+                            row * size + col,
+                            cell_count + row * size + val - 1,
+                            2 * cell_count + col * size + val - 1,
+                            3 * cell_count + rect * size + val - 1,
+                        ]
+                        .into_iter()
+                        .chain(
+                            extra_units
+                                .into_iter()
+                                .map(move |unit| 4 * cell_count + unit * size + val - 1),
+                        )
+                    })
+                    .collect();
+                links.add_row(id, &columns);
+            }
+
+            links
+                .solve(limit, deadline, cancel)
+                .into_iter()
+                .map(|row_ids| {
+                    let mut solution = Grid::with_rules_of(board);
+                    for id in row_ids {
+                        for &(row, col, val) in choices[id].iter() {
+                            solution[row as usize][col as usize] = val;
+                        }
+                    }
+                    solution
+                })
+                .collect()
+        }
+    }
+
+    // Adds each way to fill the rest of the cage with distinct values adding up to its sum to
+    // fillings, keeping given values. Cells before index are filled already.
+    fn fill_cage(
+        board: &Grid,
+        cage: &Cage,
+        index: usize,
+        filling: &mut Vec<(u8, u8, u8)>,
+        fillings: &mut Vec<Vec<(u8, u8, u8)>>,
+    ) {
+        let sum: u16 = filling.iter().map(|(_, _, val)| *val as u16).sum();
+        let Some(&(row, col)) = cage.cells.get(index) else {
+            if sum == cage.sum {
+                fillings.push(filling.clone());
+            }
+            return;
+        };
+
+        let given = board[row as usize][col as usize];
+        for val in 1..=board.shape().size {
+            if sum + val as u16 > cage.sum {
+                break;
+            }
+            if (given != 0 && given != val) || filling.iter().any(|(_, _, used)| *used == val) {
+                continue;
+            }
+            filling.push((row, col, val));
+            fill_cage(board, cage, index + 1, filling, fillings);
+            filling.pop();
+        }
+    }
+
+    // Exact cover problem solved by Knuth's Algorithm X. Nodes are kept in vectors and linked by
+    // index, node 0 is the root and nodes 1 to column count are column headers.
+    struct DancingLinks {
+        left: Vec<usize>,
+        right: Vec<usize>,
+        up: Vec<usize>,
+        down: Vec<usize>,
+        // Column header of each node
+        column: Vec<usize>,
+        // Row id of each node, given when row is added
+        row_id: Vec<usize>,
+        // Node count of each column
+        size: Vec<usize>,
+        // Search gives up once this is reached or the token is cancelled
+        deadline: Option<Instant>,
+        cancel: CancelToken,
+    }
+
+    impl DancingLinks {
+        fn new(column_count: usize) -> Self {
+            let count = column_count + 1;
+            Self {
+                left: (0..count).map(|i| (i + count - 1) % count).collect(),
+                right: (0..count).map(|i| (i + 1) % count).collect(),
+                up: (0..count).collect(),
+                down: (0..count).collect(),
+                column: (0..count).collect(),
+                row_id: vec![0; count],
+                size: vec![0; count],
+                deadline: None,
+                cancel: CancelToken::new(),
+            }
+        }
+
+        // Adds a row covering given columns, columns are counted from zero
+        fn add_row(&mut self, row_id: usize, columns: &[usize]) {
+            let first = self.left.len();
+            let last = first + columns.len() - 1;
+            for (i, column) in columns.iter().enumerate() {
+                let node = first + i;
+                let header = column + 1;
+
+                // Append to the bottom of column
+                let bottom = self.up[header];
+                self.up.push(bottom);
+                self.down.push(header);
+                self.down[bottom] = node;
+                self.up[header] = node;
+
+                self.left.push(if node == first { last } else { node - 1 });
+                self.right.push(if node == last { first } else { node + 1 });
+                self.column.push(header);
+                self.row_id.push(row_id);
+                self.size[header] += 1;
+            }
+        }
+
+        // Removes column from headers and its rows from other columns
+        fn cover(&mut self, header: usize) {
+            self.right[self.left[header]] = self.right[header];
+            self.left[self.right[header]] = self.left[header];
+
+            let mut row = self.down[header];
+            while row != header {
+                let mut node = self.right[row];
+                while node != row {
+                    self.down[self.up[node]] = self.down[node];
+                    self.up[self.down[node]] = self.up[node];
+                    self.size[self.column[node]] -= 1;
+                    node = self.right[node];
                 }
+                row = self.down[row];
             }
         }
 
-        game_board
+        // Reverts cover, in exactly reverse order
+        fn uncover(&mut self, header: usize) {
+            let mut row = self.up[header];
+            while row != header {
+                let mut node = self.left[row];
+                while node != row {
+                    self.size[self.column[node]] += 1;
+                    self.down[self.up[node]] = node;
+                    self.up[self.down[node]] = node;
+                    node = self.left[node];
+                }
+                row = self.up[row];
+            }
+
+            self.right[self.left[header]] = header;
+            self.left[self.right[header]] = header;
+        }
+
+        // Row ids of each exact cover, up to limit or as many as found until the deadline or
+        // cancellation
+        fn solve(
+            &mut self,
+            limit: usize,
+            deadline: Option<Instant>,
+            cancel: &CancelToken,
+        ) -> Vec<Vec<usize>> {
+            self.deadline = deadline;
+            self.cancel = cancel.clone();
+            let mut covers: Vec<Vec<usize>> = Vec::new();
+            self.search(limit, &mut Vec::new(), &mut covers);
+            covers
+        }
+
+        fn search(&mut self, limit: usize, chosen: &mut Vec<usize>, covers: &mut Vec<Vec<usize>>) {
+            if self.right[0] == 0 {
+                covers.push(chosen.clone());
+                return;
+            }
+
+            // Branch on the column having fewest rows
+            let mut header = self.right[0];
+            let mut column = self.right[header];
+            while column != 0 {
+                if self.size[column] < self.size[header] {
+                    header = column;
+                }
+                column = self.right[column];
+            }
+            if self.size[header] == 0 {
+                return;
+            }
+
+            self.cover(header);
+            let mut row = self.down[header];
+            while row != header {
+                chosen.push(self.row_id[row]);
+                let mut node = self.right[row];
+                while node != row {
+                    self.cover(self.column[node]);
+                    node = self.right[node];
+                }
+
+                self.search(limit, chosen, covers);
+
+                let mut node = self.left[row];
+                while node != row {
+                    self.uncover(self.column[node]);
+                    node = self.left[node];
+                }
+                chosen.pop();
+                if covers.len() >= limit || self.is_late() {
+                    break;
+                }
+                row = self.down[row];
+            }
+            self.uncover(header);
+        }
+
+        fn is_late(&self) -> bool {
+            self.cancel.is_cancelled()
+                || self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+        }
+    }
+
+    // Candidates of each cell as bit mask, bit n is set when value n is possible.
+    // Filled cells have no candidates.
+    pub type Candidates = Grid<u32>;
+
+    // Logical solving techniques, ordered from easiest to hardest
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum Technique {
+        NakedSingle,
+        HiddenSingle,
+        // Killer only, the rule of 45 for a cell left out of or sticking out of cages of a unit
+        InnieOutie,
+        PointingPair,
+        BoxLineReduction,
+        NakedPair,
+        HiddenPair,
+        NakedTriple,
+        HiddenTriple,
+        XWing,
+        SimpleColoring,
+        XYWing,
+        Swordfish,
+    }
+
+    impl Technique {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Technique::NakedSingle => "Naked single",
+                Technique::HiddenSingle => "Hidden single",
+                Technique::InnieOutie => "Innie/outie",
+                Technique::PointingPair => "Pointing pair",
+                Technique::BoxLineReduction => "Box/line reduction",
+                Technique::NakedPair => "Naked pair",
+                Technique::HiddenPair => "Hidden pair",
+                Technique::NakedTriple => "Naked triple",
+                Technique::HiddenTriple => "Hidden triple",
+                Technique::XWing => "X-Wing",
+                Technique::SimpleColoring => "Simple coloring",
+                Technique::XYWing => "XY-Wing",
+                Technique::Swordfish => "Swordfish",
+            }
+        }
+
+        // Score of a single use of the technique, used for rating puzzles
+        pub fn weight(&self) -> u32 {
+            match self {
+                Technique::NakedSingle => 1,
+                Technique::HiddenSingle => 2,
+                Technique::InnieOutie => 4,
+                Technique::PointingPair => 5,
+                Technique::BoxLineReduction => 5,
+                Technique::NakedPair => 8,
+                Technique::HiddenPair => 10,
+                Technique::NakedTriple => 12,
+                Technique::HiddenTriple => 14,
+                Technique::XWing => 20,
+                Technique::SimpleColoring => 24,
+                Technique::XYWing => 26,
+                Technique::Swordfish => 30,
+            }
+        }
+    }
+
+    // Difficulty of a puzzle based on the techniques needed to solve it
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+    pub struct Rating {
+        // Hardest technique needed, None if logical techniques aren't enough to solve the puzzle
+        pub hardest: Option<Technique>,
+        // How many times hardest technique is needed
+        pub hardest_count: u32,
+        // Sum of weights of all steps, used to compare puzzles with same hardest technique
+        pub score: u32,
+    }
+
+    impl Rating {
+        pub fn is_in(&self, band: &RangeInclusive<Technique>) -> bool {
+            match self.hardest {
+                Some(technique) => band.contains(&technique),
+                None => false,
+            }
+        }
+    }
+
+    // A single logical step
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Deduction {
+        pub technique: Technique,
+        // Cells forming the pattern which lead to the deduction
+        pub cells: Vec<(u8, u8)>,
+        // Value placed by the step as (row, col, val)
+        pub placement: Option<(u8, u8, u8)>,
+        // Candidates removed by the step as (row, col, val)
+        pub eliminations: Vec<(u8, u8, u8)>,
+    }
+
+    pub fn candidates(board: &Grid) -> Candidates {
+        let shape = board.shape();
+        let bit_board = BitBoard::new(board);
+        let mut candidates: Candidates = Grid::with_rules_of(board);
+        for row in 0..shape.size {
+            for col in 0..shape.size {
+                candidates[row as usize][col as usize] = bit_board.candidates(row, col);
+            }
+        }
+
+        candidates
+    }
+
+    // Finds the easiest deduction available on the board
+    pub fn find_deduction(board: &Grid, candidates: &Candidates) -> Option<Deduction> {
+        find_naked_single(candidates)
+            .or_else(|| find_hidden_single(candidates))
+            .or_else(|| find_innie_outie(board, candidates))
+            .or_else(|| find_pointing(candidates))
+            .or_else(|| find_box_line_reduction(candidates))
+            .or_else(|| find_naked_subset(candidates, 2))
+            .or_else(|| find_hidden_subset(candidates, 2))
+            .or_else(|| find_naked_subset(candidates, 3))
+            .or_else(|| find_hidden_subset(candidates, 3))
+            .or_else(|| find_fish(candidates, 2))
+            .or_else(|| find_simple_coloring(candidates))
+            .or_else(|| find_xy_wing(candidates))
+            .or_else(|| find_fish(candidates, 3))
+    }
+
+    pub fn apply_deduction(board: &mut Grid, candidates: &mut Candidates, deduction: &Deduction) {
+        let shape = board.shape();
+        if let Some((row, col, val)) = deduction.placement {
+            board[row as usize][col as usize] = val;
+            candidates[row as usize][col as usize] = 0;
+            for r in 0..shape.size {
+                for c in 0..shape.size {
+                    if are_related(board, (row, col), (r, c)) {
+                        candidates[r as usize][c as usize] &= !(1 << val);
+                    }
+                }
+            }
+            // Sum of the cage limits the candidates of its other cells
+            if let Some(cage) = board.cage_at(row, col) {
+                let bit_board = BitBoard::new(board);
+                for &(r, c) in cage.cells.iter() {
+                    candidates[r as usize][c as usize] &= bit_board.candidates(r, c);
+                }
+            }
+        }
+        for &(row, col, val) in deduction.eliminations.iter() {
+            candidates[row as usize][col as usize] &= !(1 << val);
+        }
+    }
+
+    // Solves the board step by step with logical techniques only
+    // @return deductions in order and the board after the last one. Returned board has empty
+    // cells if techniques weren't enough to solve it
+    pub fn solve_logically(board: &Grid) -> (Vec<Deduction>, Grid) {
+        let mut board = board.clone();
+        let mut candidates = candidates(&board);
+        let mut deductions: Vec<Deduction> = Vec::new();
+
+        while let Some(deduction) = find_deduction(&board, &candidates) {
+            apply_deduction(&mut board, &mut candidates, &deduction);
+            deductions.push(deduction);
+        }
+
+        (deductions, board)
+    }
+
+    pub fn rate(board: &Grid) -> Rating {
+        let (deductions, solved_board) = solve_logically(board);
+        let mut rating = Rating {
+            hardest: None,
+            hardest_count: 0,
+            score: deductions.iter().map(|d| d.technique.weight()).sum(),
+        };
+        if !is_finished(&solved_board) {
+            return rating;
+        }
+
+        for deduction in deductions.iter() {
+            if rating.hardest == Some(deduction.technique) {
+                rating.hardest_count += 1;
+            } else if rating.hardest < Some(deduction.technique) {
+                rating.hardest = Some(deduction.technique);
+                rating.hardest_count = 1;
+            }
+        }
+
+        rating
+    }
+
+    fn mask_values(mask: u32) -> impl Iterator<Item = u8> {
+        (1..=16).filter(move |val| mask & (1 << val) != 0)
+    }
+
+    // All index combinations of given size out of 0..count
+    fn combinations(count: usize, size: usize) -> Vec<Vec<usize>> {
+        if size == 0 {
+            return vec![Vec::new()];
+        }
+        let mut all_combinations: Vec<Vec<usize>> = Vec::new();
+        for last in (size - 1)..count {
+            for mut combination in combinations(last, size - 1) {
+                combination.push(last);
+                all_combinations.push(combination);
+            }
+        }
+        all_combinations
+    }
+
+    // Values of a unit add up to the sum of all values. So if cages inside a unit cover all of
+    // its empty cells but one, that cell has the rest of the sum (innie). If cages covering a
+    // unit stick out of it by a single empty cell, that cell has the excess (outie).
+    fn find_innie_outie(board: &Grid, candidates: &Candidates) -> Option<Deduction> {
+        let size = board.shape().size as i32;
+        let unit_sum = size * (size + 1) / 2;
+        let cage_map = board.cage_map();
+        let value = |(row, col): (u8, u8)| board[row as usize][col as usize] as i32;
+        let cage_of = |(row, col): (u8, u8)| cage_map[row as usize][col as usize];
+        // Value is only trusted if it is a candidate, boards with conflicts may give others
+        let placement = |cell: (u8, u8), val: i32, cells: Vec<(u8, u8)>| {
+            let (row, col) = cell;
+            ((1..=size).contains(&val) && candidates[row as usize][col as usize] & 1 << val != 0)
+                .then(|| Deduction {
+                    technique: Technique::InnieOutie,
+                    cells,
+                    placement: Some((row, col, val as u8)),
+                    eliminations: Vec::new(),
+                })
+        };
+
+        if board.cages().is_empty() {
+            return None;
+        }
+        for unit in 0..board.unit_count() {
+            let cells: Vec<(u8, u8)> = board.unit_cells(unit).collect();
+            let mut cages: Vec<usize> = cells.iter().filter_map(|cell| cage_of(*cell)).collect();
+            cages.sort();
+            cages.dedup();
+            let is_inside = |cage: usize| {
+                board.cages()[cage]
+                    .cells
+                    .iter()
+                    .all(|cell| cells.contains(cell))
+            };
+
+            // Innie, cells not in a cage inside the unit are known but one
+            let mut rest = unit_sum;
+            let mut empty_cells: Vec<(u8, u8)> = Vec::new();
+            for &cell in cells.iter() {
+                match cage_of(cell) {
+                    Some(cage) if is_inside(cage) => {}
+                    _ if value(cell) != 0 => rest -= value(cell),
+                    _ => empty_cells.push(cell),
+                }
+            }
+            for &cage in cages.iter().filter(|cage| is_inside(**cage)) {
+                rest -= board.cages()[cage].sum as i32;
+            }
+            if let [cell] = empty_cells[..] {
+                if let Some(deduction) = placement(cell, rest, cells.clone()) {
+                    return Some(deduction);
+                }
+            }
+
+            // Outie, every empty cell of the unit is in a cage and cells of the cages out of the
+            // unit are known but one
+            if cells
+                .iter()
+                .any(|cell| cage_of(*cell).is_none() && value(*cell) == 0)
+            {
+                continue;
+            }
+            let mut excess = -unit_sum;
+            let mut empty_cells: Vec<(u8, u8)> = Vec::new();
+            for &cell in cells.iter() {
+                if cage_of(cell).is_none() {
+                    excess += value(cell);
+                }
+            }
+            for &cage in cages.iter() {
+                excess += board.cages()[cage].sum as i32;
+                for &cell in board.cages()[cage].cells.iter() {
+                    if cells.contains(&cell) {
+                        continue;
+                    }
+                    match value(cell) {
+                        0 => empty_cells.push(cell),
+                        val => excess -= val,
+                    }
+                }
+            }
+            if let [cell] = empty_cells[..] {
+                if let Some(deduction) = placement(cell, excess, cells) {
+                    return Some(deduction);
+                }
+            }
+        }
+        None
+    }
+
+    fn find_naked_single(candidates: &Candidates) -> Option<Deduction> {
+        let size = candidates.shape().size;
+        for row in 0..size {
+            for col in 0..size {
+                let mask = candidates[row as usize][col as usize];
+                if mask.count_ones() == 1 {
+                    return Some(Deduction {
+                        technique: Technique::NakedSingle,
+                        cells: vec![(row, col)],
+                        placement: Some((row, col, mask.trailing_zeros() as u8)),
+                        eliminations: Vec::new(),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn find_hidden_single(candidates: &Candidates) -> Option<Deduction> {
+        let shape = candidates.shape();
+        for unit in 0..candidates.unit_count() {
+            for val in 1..=shape.size {
+                let mut places = candidates
+                    .unit_cells(unit)
+                    .filter(|(r, c)| candidates[*r as usize][*c as usize] & (1 << val) != 0);
+                if let (Some((row, col)), None) = (places.next(), places.next()) {
+                    return Some(Deduction {
+                        technique: Technique::HiddenSingle,
+                        cells: vec![(row, col)],
+                        placement: Some((row, col, val)),
+                        eliminations: Vec::new(),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn find_naked_subset(candidates: &Candidates, size: usize) -> Option<Deduction> {
+        let technique = if size == 2 {
+            Technique::NakedPair
+        } else {
+            Technique::NakedTriple
+        };
+        for unit in 0..candidates.unit_count() {
+            let empty_cells: Vec<(u8, u8)> = candidates
+                .unit_cells(unit)
+                .filter(|(r, c)| candidates[*r as usize][*c as usize] != 0)
+                .collect();
+            for combination in combinations(empty_cells.len(), size) {
+                let subset: Vec<(u8, u8)> = combination.iter().map(|&i| empty_cells[i]).collect();
+                let mask = subset.iter().fold(0, |mask, (r, c)| {
+                    mask | candidates[*r as usize][*c as usize]
+                });
+                if mask.count_ones() as usize != size {
+                    continue;
+                }
+                let mut eliminations: Vec<(u8, u8, u8)> = Vec::new();
+                for &(row, col) in empty_cells.iter().filter(|cell| !subset.contains(cell)) {
+                    for val in mask_values(candidates[row as usize][col as usize] & mask) {
+                        eliminations.push((row, col, val));
+                    }
+                }
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique,
+                        cells: subset,
+                        placement: None,
+                        eliminations,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn find_hidden_subset(candidates: &Candidates, size: usize) -> Option<Deduction> {
+        let technique = if size == 2 {
+            Technique::HiddenPair
+        } else {
+            Technique::HiddenTriple
+        };
+        let shape = candidates.shape();
+        for unit in 0..candidates.unit_count() {
+            let cells: Vec<(u8, u8)> = candidates.unit_cells(unit).collect();
+            // Bit i of places[val] is set when val is possible in cells[i]
+            let mut places = [0u32; 17];
+            for (i, (r, c)) in cells.iter().enumerate() {
+                for val in mask_values(candidates[*r as usize][*c as usize]) {
+                    places[val as usize] |= 1 << i;
+                }
+            }
+            let values: Vec<u8> = (1..=shape.size)
+                .filter(|val| places[*val as usize] != 0)
+                .collect();
+            for combination in combinations(values.len(), size) {
+                let subset_mask = combination
+                    .iter()
+                    .fold(0u32, |mask, &i| mask | 1 << values[i]);
+                let cells_mask = combination
+                    .iter()
+                    .fold(0u32, |mask, &i| mask | places[values[i] as usize]);
+                if cells_mask.count_ones() as usize != size {
+                    continue;
+                }
+                let subset: Vec<(u8, u8)> = (0..cells.len())
+                    .filter(|i| cells_mask & (1 << i) != 0)
+                    .map(|i| cells[i])
+                    .collect();
+                let mut eliminations: Vec<(u8, u8, u8)> = Vec::new();
+                for &(row, col) in subset.iter() {
+                    for val in mask_values(candidates[row as usize][col as usize] & !subset_mask) {
+                        eliminations.push((row, col, val));
+                    }
+                }
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique,
+                        cells: subset,
+                        placement: None,
+                        eliminations,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // Value is possible only in one row or col of a rect, so it can't be elsewhere on that line
+    fn find_pointing(candidates: &Candidates) -> Option<Deduction> {
+        let shape = candidates.shape();
+        let size = shape.size as usize;
+        for unit in 2 * size..3 * size {
+            for val in 1..=shape.size {
+                let places: Vec<(u8, u8)> = candidates
+                    .unit_cells(unit)
+                    .filter(|(r, c)| candidates[*r as usize][*c as usize] & (1 << val) != 0)
+                    .collect();
+                if places.len() < 2 {
+                    continue;
+                }
+                let (row, col) = places[0];
+                let line_unit = if places.iter().all(|cell| cell.0 == row) {
+                    row as usize
+                } else if places.iter().all(|cell| cell.1 == col) {
+                    size + col as usize
+                } else {
+                    continue;
+                };
+                let eliminations: Vec<(u8, u8, u8)> = candidates
+                    .unit_cells(line_unit)
+                    .filter(|(r, c)| {
+                        !places.contains(&(*r, *c))
+                            && candidates.box_index(*r, *c) != candidates.box_index(row, col)
+                            && candidates[*r as usize][*c as usize] & (1 << val) != 0
+                    })
+                    .map(|(r, c)| (r, c, val))
+                    .collect();
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique: Technique::PointingPair,
+                        cells: places,
+                        placement: None,
+                        eliminations,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // Value is possible only in one rect along a row or col, so it can't be elsewhere in that rect
+    fn find_box_line_reduction(candidates: &Candidates) -> Option<Deduction> {
+        let shape = candidates.shape();
+        let size = shape.size as usize;
+        for unit in 0..2 * size {
+            for val in 1..=shape.size {
+                let places: Vec<(u8, u8)> = candidates
+                    .unit_cells(unit)
+                    .filter(|(r, c)| candidates[*r as usize][*c as usize] & (1 << val) != 0)
+                    .collect();
+                if places.len() < 2 {
+                    continue;
+                }
+                let (row, col) = places[0];
+                let rect = candidates.box_index(row, col);
+                if !places
+                    .iter()
+                    .all(|cell| candidates.box_index(cell.0, cell.1) == rect)
+                {
+                    continue;
+                }
+                let rect_unit = 2 * size + rect as usize;
+                let eliminations: Vec<(u8, u8, u8)> = candidates
+                    .unit_cells(rect_unit)
+                    .filter(|(r, c)| {
+                        let on_line = if unit < size { *r == row } else { *c == col };
+                        !on_line && candidates[*r as usize][*c as usize] & (1 << val) != 0
+                    })
+                    .map(|(r, c)| (r, c, val))
+                    .collect();
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique: Technique::BoxLineReduction,
+                        cells: places,
+                        placement: None,
+                        eliminations,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // X-Wing for size 2, Swordfish for size 3
+    fn find_fish(candidates: &Candidates, size: usize) -> Option<Deduction> {
+        let technique = if size == 2 {
+            Technique::XWing
+        } else {
+            Technique::Swordfish
+        };
+        let lines_count = candidates.shape().size as usize;
+        for val in 1..=candidates.shape().size {
+            for by_row in [true, false] {
+                // Maps (line, cross) index pair of the orientation to (row, col)
+                let cell = |line: usize, cross: usize| {
+                    if by_row {
+                        (line as u8, cross as u8)
+                    } else {
+                        (cross as u8, line as u8)
+                    }
+                };
+                let mut line_masks = [0u32; 16];
+                for (line, mask) in line_masks.iter_mut().take(lines_count).enumerate() {
+                    for cross in 0..lines_count {
+                        let (r, c) = cell(line, cross);
+                        if candidates[r as usize][c as usize] & (1 << val) != 0 {
+                            *mask |= 1 << cross;
+                        }
+                    }
+                }
+                let lines: Vec<usize> = (0..lines_count)
+                    .filter(|line| (2..=size as u32).contains(&line_masks[*line].count_ones()))
+                    .collect();
+                for combination in combinations(lines.len(), size) {
+                    let base: Vec<usize> = combination.iter().map(|&i| lines[i]).collect();
+                    let cover = base
+                        .iter()
+                        .fold(0u32, |mask, &line| mask | line_masks[line]);
+                    if cover.count_ones() as usize != size {
+                        continue;
+                    }
+                    let mut eliminations: Vec<(u8, u8, u8)> = Vec::new();
+                    for line in (0..lines_count).filter(|line| !base.contains(line)) {
+                        for cross in (0..lines_count)
+                            .filter(|cross| line_masks[line] & cover & (1 << cross) != 0)
+                        {
+                            let (r, c) = cell(line, cross);
+                            eliminations.push((r, c, val));
+                        }
+                    }
+                    if !eliminations.is_empty() {
+                        let cells: Vec<(u8, u8)> = base
+                            .iter()
+                            .flat_map(|&line| {
+                                (0..lines_count)
+                                    .filter(move |cross| line_masks[line] & (1 << cross) != 0)
+                                    .map(move |cross| cell(line, cross))
+                            })
+                            .collect();
+                        return Some(Deduction {
+                            technique,
+                            cells,
+                            placement: None,
+                            eliminations,
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Pivot cell {x, y} sees pincers {x, z} and {y, z}, so z can't be in cells seeing both pincers
+    fn find_xy_wing(candidates: &Candidates) -> Option<Deduction> {
+        let shape = candidates.shape();
+        let mut bivalue_cells: Vec<(u8, u8)> = Vec::new();
+        for row in 0..shape.size {
+            for col in 0..shape.size {
+                if candidates[row as usize][col as usize].count_ones() == 2 {
+                    bivalue_cells.push((row, col));
+                }
+            }
+        }
+        let mask_of = |cell: (u8, u8)| candidates[cell.0 as usize][cell.1 as usize];
+
+        for &pivot in bivalue_cells.iter() {
+            let pivot_mask = mask_of(pivot);
+            for &pincer1 in bivalue_cells.iter() {
+                let pincer1_mask = mask_of(pincer1);
+                if pincer1 == pivot
+                    || !are_related(candidates, pivot, pincer1)
+                    || (pivot_mask & pincer1_mask).count_ones() != 1
+                {
+                    continue;
+                }
+                let z_mask = pincer1_mask & !pivot_mask;
+                let pincer2_mask = (pivot_mask & !pincer1_mask) | z_mask;
+                for &pincer2 in bivalue_cells.iter() {
+                    if pincer2 == pivot
+                        || pincer2 == pincer1
+                        || mask_of(pincer2) != pincer2_mask
+                        || !are_related(candidates, pivot, pincer2)
+                    {
+                        continue;
+                    }
+                    let z = z_mask.trailing_zeros() as u8;
+                    let mut eliminations: Vec<(u8, u8, u8)> = Vec::new();
+                    for row in 0..shape.size {
+                        for col in 0..shape.size {
+                            let cell = (row, col);
+                            if cell != pivot
+                                && cell != pincer1
+                                && cell != pincer2
+                                && mask_of(cell) & z_mask != 0
+                                && are_related(candidates, cell, pincer1)
+                                && are_related(candidates, cell, pincer2)
+                            {
+                                eliminations.push((row, col, z));
+                            }
+                        }
+                    }
+                    if !eliminations.is_empty() {
+                        return Some(Deduction {
+                            technique: Technique::XYWing,
+                            cells: vec![pivot, pincer1, pincer2],
+                            placement: None,
+                            eliminations,
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Colors chains of conjugate pairs for a value. If two cells of same color see each other
+    // that color is false; cells seeing both colors can't have the value.
+    fn find_simple_coloring(candidates: &Candidates) -> Option<Deduction> {
+        let shape = candidates.shape();
+        for val in 1..=shape.size {
+            let has_val =
+                |cell: (u8, u8)| candidates[cell.0 as usize][cell.1 as usize] & (1 << val) != 0;
+
+            // Conjugate pairs: units where value is possible in exactly two cells
+            let mut links: Vec<((u8, u8), (u8, u8))> = Vec::new();
+            for unit in 0..candidates.unit_count() {
+                let places: Vec<(u8, u8)> = candidates
+                    .unit_cells(unit)
+                    .filter(|cell| has_val(*cell))
+                    .collect();
+                if places.len() == 2 {
+                    links.push((places[0], places[1]));
+                }
+            }
+
+            let mut colors: Grid<Option<bool>> = Grid::new(shape);
+            for &(start, _) in links.iter() {
+                if colors[start.0 as usize][start.1 as usize].is_some() {
+                    continue;
+                }
+                // Color the chain starting from this cell
+                let mut chain: Vec<(u8, u8)> = vec![start];
+                colors[start.0 as usize][start.1 as usize] = Some(true);
+                let mut index = 0;
+                while index < chain.len() {
+                    let cell = chain[index];
+                    let color = colors[cell.0 as usize][cell.1 as usize].unwrap();
+                    for &(a, b) in links.iter() {
+                        let other = if a == cell {
+                            b
+                        } else if b == cell {
+                            a
+                        } else {
+                            continue;
+                        };
+                        if colors[other.0 as usize][other.1 as usize].is_none() {
+                            colors[other.0 as usize][other.1 as usize] = Some(!color);
+                            chain.push(other);
+                        }
+                    }
+                    index += 1;
+                }
+                if chain.len() < 3 {
+                    continue;
+                }
+
+                let color_of = |cell: &(u8, u8)| colors[cell.0 as usize][cell.1 as usize];
+                let mut eliminations: Vec<(u8, u8, u8)> = Vec::new();
+
+                // Color wrap
+                for color in [true, false] {
+                    let same_color: Vec<(u8, u8)> = chain
+                        .iter()
+                        .filter(|cell| color_of(cell) == Some(color))
+                        .copied()
+                        .collect();
+                    let wrapped = same_color.iter().enumerate().any(|(i, a)| {
+                        same_color[i + 1..]
+                            .iter()
+                            .any(|b| are_related(candidates, *a, *b))
+                    });
+                    if wrapped {
+                        eliminations = same_color.iter().map(|(r, c)| (*r, *c, val)).collect();
+                        break;
+                    }
+                }
+
+                // Color trap
+                if eliminations.is_empty() {
+                    for row in 0..shape.size {
+                        for col in 0..shape.size {
+                            let cell = (row, col);
+                            if !has_val(cell) || chain.contains(&cell) {
+                                continue;
+                            }
+                            let sees = |color: bool| {
+                                chain.iter().any(|other| {
+                                    color_of(other) == Some(color)
+                                        && are_related(candidates, cell, *other)
+                                })
+                            };
+                            if sees(true) && sees(false) {
+                                eliminations.push((row, col, val));
+                            }
+                        }
+                    }
+                }
+
+                if !eliminations.is_empty() {
+                    return Some(Deduction {
+                        technique: Technique::SimpleColoring,
+                        cells: chain,
+                        placement: None,
+                        eliminations,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // Removes cells as long as board can be solved without a technique harder than the band of
+    // options allows. Cells symmetric to each other are removed together. Stops early at the
+    // deadline, board is still uniquely solvable then but may be easier. With finish_removal
+    // LATE_REMOVAL_TRIES more cells are tried after the deadline, so the board has empty cells.
+    fn adjust_difficulty(
+        solved_board: &Grid,
+        options: &GenerationOptions,
+        seed: u64,
+        finish_removal: bool,
+        context: &GenerationContext,
+    ) -> (Rating, Grid) {
+        let GenerationContext {
+            deadline, cancel, ..
+        } = *context;
+        let shape = solved_board.shape();
+        let mut board = solved_board.clone();
+        let mut rating = rate(&board);
+        let max_technique = *options.band.end();
+
+        // Remove random cell and verify board has still one solution and is still in band
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut all_indexes: Vec<usize> = (0..shape.cell_count()).collect();
+        all_indexes.shuffle(&mut rng);
+        let mut late_tries = 0;
+        for (removed, index) in all_indexes.iter().copied().enumerate() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                late_tries += 1;
+            }
+            if cancel.is_cancelled()
+                || (late_tries > 0 && !finish_removal)
+                || late_tries > LATE_REMOVAL_TRIES
+            {
+                break;
+            }
+
+            let row = (index / shape.size as usize) as u8;
+            let col = (index % shape.size as usize) as u8;
+            // Already removed along with a symmetric cell
+            if board[row as usize][col as usize] == 0 {
+                continue;
+            }
+
+            let orbit = options.symmetry.orbit(shape.size, row, col);
+            let values: Vec<u8> = orbit
+                .iter()
+                .map(|(row, col)| board[*row as usize][*col as usize])
+                .collect();
+            for (row, col) in orbit.iter() {
+                board[*row as usize][*col as usize] = 0; // Remove data from cell
+            }
+
+            // Only a board with one solution is rated, searching is much faster than rating
+            let new_rating = (count_solutions(&board, 2) == 1).then(|| rate(&board));
+            if let Some(new_rating) =
+                new_rating.filter(|rating| rating.is_in(&(Technique::NakedSingle..=max_technique)))
+            {
+                rating = new_rating;
+            } else {
+                // Revert removal
+                for ((row, col), val) in orbit.iter().zip(values) {
+                    board[*row as usize][*col as usize] = val;
+                }
+            }
+
+            if let Some(sender) = &context.progress {
+                // Receiver may be gone already, progress is just informative
+                let _ = sender.send(Progress {
+                    attempt: context.attempt,
+                    tried_cells: removed as u16 + 1,
+                    clues: board.cells().iter().filter(|val| **val != 0).count() as u16,
+                    rating,
+                });
+            }
+        }
+
+        if options.minimal {
+            // Remove the rest of values which aren't needed for a unique solution, as long as
+            // board stays in band. If some are kept for the band only, they are removed anyway
+            // so that the board is still minimal when it is used as a fallback.
+            let mut kept_for_band = false;
+            let mut finished =
+                remove_unneeded_values(&mut board, &all_indexes, deadline, cancel, |board| {
+                    let new_rating = rate(board);
+                    if new_rating.is_in(&(Technique::NakedSingle..=max_technique)) {
+                        rating = new_rating;
+                        true
+                    } else {
+                        kept_for_band = true;
+                        false
+                    }
+                });
+            if finished && kept_for_band {
+                finished =
+                    remove_unneeded_values(&mut board, &all_indexes, deadline, cancel, |_| true);
+                rating = rate(&board);
+            }
+            debug_assert!(!finished || is_minimal(&board));
+        }
+
+        (rating, board)
+    }
+
+    // Removes values of cells at indexes, in order, which aren't needed for a unique solution
+    // and whose removal is accepted. Values needed once are needed later on as well, since
+    // removing more values only adds solutions, so a single pass is enough for them.
+    // @return false if stopped at the deadline or cancelled
+    fn remove_unneeded_values(
+        board: &mut Grid,
+        indexes: &[usize],
+        deadline: Option<Instant>,
+        cancel: &CancelToken,
+        mut accept: impl FnMut(&Grid) -> bool,
+    ) -> bool {
+        let size = board.shape().size as usize;
+        for &index in indexes {
+            if cancel.is_cancelled() || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return false;
+            }
+
+            let (row, col) = (index / size, index % size);
+            let val = board[row][col];
+            if val == 0 {
+                continue;
+            }
+            board[row][col] = 0;
+            if count_solutions(board, 2) != 1 || !accept(board) {
+                board[row][col] = val;
+            }
+        }
+
+        true
+    }
+
+    // State of a generation which boards being generated share
+    struct GenerationContext<'a> {
+        // Time budget of the options is exceeded after it
+        deadline: Option<Instant>,
+        cancel: &'a CancelToken,
+        // Progress of each board is sent here if given
+        progress: Option<mpsc::Sender<Progress>>,
+        // Number of solved boards created so far, starting from 1
+        attempt: u32,
+    }
+
+    // Flag shared with the generator to stop it from another thread
+    #[derive(Clone, Debug, Default)]
+    pub struct CancelToken(Arc<AtomicBool>);
+
+    impl CancelToken {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn cancel(&self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+
+        pub fn is_cancelled(&self) -> bool {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    // State of a board being generated, sent after each cell removal is tried
+    #[derive(Copy, Clone, Debug)]
+    pub struct Progress {
+        // Number of solved boards created so far, starting from 1
+        pub attempt: u32,
+        // Cells tried for removal out of all cells of the board
+        pub tried_cells: u16,
+        // Remaining clues
+        pub clues: u16,
+        pub rating: Rating,
+    }
+
+    // Settings of the generator
+    #[derive(Clone, Debug)]
+    pub struct GenerationOptions {
+        pub shape: Shape,
+        pub variant: Variant,
+        // Extra units every generated board has
+        pub constraints: Vec<Constraint>,
+        // Boxes are replaced with random connected regions
+        pub jigsaw: bool,
+        // Range of the hardest technique needed to solve the puzzle
+        pub band: RangeInclusive<Technique>,
+        pub seed: u64,
+        // Once exceeded, the best board found so far is returned even if it isn't in band
+        pub time_budget: Option<Duration>,
+        // Number of worker threads, at least 1
+        pub threads: usize,
+        pub symmetry: Symmetry,
+        // Every value of the board is needed for a unique solution. Values are removed one by one
+        // for that, so symmetry may be lost.
+        pub minimal: bool,
+    }
+
+    // Rules of the generated puzzle on top of the classic ones
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Variant {
+        Classic,
+        // Cages of up to max_cage_size cells replace most givens
+        Killer { max_cage_size: u8 },
+    }
+
+    // Symmetry of the clue pattern of generated puzzles
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Symmetry {
+        None,
+        // Rotating 180 degrees
+        Rotational,
+        // Mirroring over the horizontal middle line
+        MirrorHorizontal,
+        // Mirroring over the vertical middle line
+        MirrorVertical,
+        // Mirroring over the main diagonal
+        Diagonal,
+        // All rotations and mirrors of the square
+        Dihedral,
+    }
+
+    impl Symmetry {
+        // Cells mapped to each other by the symmetry on a board of given size, including the
+        // cell itself
+        pub fn orbit(&self, size: u8, row: u8, col: u8) -> Vec<(u8, u8)> {
+            let last = size - 1;
+            let mut cells: Vec<(u8, u8)> = vec![(row, col)];
+            match self {
+                Symmetry::None => {}
+                Symmetry::Rotational => cells.push((last - row, last - col)),
+                Symmetry::MirrorHorizontal => cells.push((last - row, col)),
+                Symmetry::MirrorVertical => cells.push((row, last - col)),
+                Symmetry::Diagonal => cells.push((col, row)),
+                Symmetry::Dihedral => cells.extend([
+                    (col, last - row),
+                    (last - row, last - col),
+                    (last - col, row),
+                    (last - row, col),
+                    (row, last - col),
+                    (col, row),
+                    (last - col, last - row),
+                ]),
+            }
+            // Cells on an axis are mapped to themselves
+            cells.sort();
+            cells.dedup();
+            cells
+        }
+    }
+
+    // Generates boards until one is rated in the band, i.e. hardest technique needed to solve
+    // it is in the given range. The best board out of band is returned once time budget is
+    // exceeded or after MAX_ATTEMPTS rounds of solved boards. Same seed always generates the
+    // same board, unless time budget is exceeded. Progress of each board is sent if a sender is
+    // given, None is returned once cancelled.
+    pub fn generate_initial_board(
+        options: &GenerationOptions,
+        cancel: &CancelToken,
+        progress: Option<mpsc::Sender<Progress>>,
+    ) -> Option<(Rating, Grid)> {
+        let deadline = options
+            .time_budget
+            .map(|time_budget| Instant::now() + time_budget);
+        if let Variant::Killer { max_cage_size } = options.variant {
+            let context = GenerationContext {
+                deadline,
+                cancel,
+                progress,
+                attempt: 1,
+            };
+            return generate_killer_board(options, max_cage_size, &context);
+        }
+
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let mut attempt: u32 = 0;
+        // Best board out of band. Boards are easier than the band unless they are minimal, harder
+        // ones may not even be solved logically so they are least preferred.
+        let mut fallback: Option<(Rating, Grid)> = None;
+        let fallback_key = |rating: &Rating| {
+            let solvable = rating
+                .hardest
+                .is_some_and(|technique| technique <= *options.band.end());
+            (solvable, rating.hardest, rating.score)
+        };
+        loop {
+            let solutions = create_solved_boards(options, &mut rng, cancel, deadline);
+            if cancel.is_cancelled() {
+                return None;
+            }
+            attempt += 1;
+
+            let mut jobs: Vec<Job<(Rating, Grid)>> = Vec::new();
+            for (index, solved_board) in solutions.into_iter().enumerate() {
+                // Each job gets its own seed, drawn in order of solutions
+                let seed: u64 = rng.random();
+                let context = GenerationContext {
+                    deadline,
+                    cancel,
+                    progress: progress.clone(),
+                    attempt,
+                };
+                // First board has a few cells tried even if time budget is exceeded, so there
+                // is always a puzzle to fall back on, not a board with no empty cells
+                let finish_removal = index == 0;
+                jobs.push(Box::new(move || {
+                    adjust_difficulty(&solved_board, options, seed, finish_removal, &context)
+                }));
+            }
+
+            // Prefer the hardest one among boards in band
+            let mut game_board: Option<(Rating, Grid)> = None;
+            for (rating, board) in run_jobs(jobs, options.threads) {
+                if !rating.is_in(&options.band) {
+                    if fallback
+                        .as_ref()
+                        .is_none_or(|(best, _)| fallback_key(&rating) > fallback_key(best))
+                    {
+                        fallback = Some((rating, board));
+                    }
+                    continue;
+                }
+                if game_board
+                    .as_ref()
+                    .is_none_or(|(best, _)| rating.score > best.score)
+                {
+                    game_board = Some((rating, board));
+                }
+            }
+
+            if cancel.is_cancelled() {
+                return None;
+            }
+            if game_board.is_some() {
+                return game_board;
+            }
+            // Some bands are rarely reached on some boards, e.g. hard ones on 4x4 or 16x16
+            // boards, so without time budget the search gives up after a number of attempts
+            if fallback.is_some()
+                && (attempt >= MAX_ATTEMPTS
+                    || deadline.is_some_and(|deadline| Instant::now() >= deadline))
+            {
+                return fallback;
+            }
+        }
+    }
+
+    // Generates a killer board, cages are made on a solved board and then givens are added until
+    // it can be solved logically, which makes its solution unique. Givens which aren't needed
+    // for that are removed at the end, so every given is needed unless time budget is exceeded.
+    // Band, symmetry and minimal of options don't apply, the board is rated but returned
+    // whatever its rating is.
+    fn generate_killer_board(
+        options: &GenerationOptions,
+        max_cage_size: u8,
+        context: &GenerationContext,
+    ) -> Option<(Rating, Grid)> {
+        let shape = options.shape;
+        let size = shape.size as usize;
+        let GenerationContext {
+            deadline, cancel, ..
+        } = *context;
+        let is_late = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let solved_board = create_solved_boards(options, &mut rng, cancel, deadline)
+            .into_iter()
+            .next()?;
+
+        let mut board = Grid::with_rules_of(&solved_board);
+        board.set_cages(create_cages(&solved_board, max_cage_size, &mut rng));
+
+        // Give the value of a random cell logical solving stops at, until it solves the board.
+        // Once time budget is exceeded, all of those cells are given at once.
+        loop {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            let (_, solved_logically) = solve_logically(&board);
+            let unsolved: Vec<usize> = (0..shape.cell_count())
+                .filter(|index| solved_logically.cells()[*index] == 0)
+                .collect();
+            if unsolved.is_empty() {
+                break;
+            }
+            if is_late() {
+                for index in unsolved {
+                    board[index / size][index % size] = solved_board[index / size][index % size];
+                }
+                continue;
+            }
+            let index = unsolved[rng.random_range(0..unsolved.len())];
+            board[index / size][index % size] = solved_board[index / size][index % size];
+        }
+
+        let mut givens: Vec<usize> = (0..shape.cell_count())
+            .filter(|index| board.cells()[*index] != 0)
+            .collect();
+        givens.shuffle(&mut rng);
+        let mut rating = rate(&board);
+        let mut clues = givens.len() as u16;
+        for (tried, index) in givens.iter().copied().enumerate() {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            // Board is solved logically already, it just keeps more givens
+            if is_late() {
+                break;
+            }
+            let (row, col) = (index / size, index % size);
+            let val = board[row][col];
+            board[row][col] = 0;
+            let new_rating = (count_solutions(&board, 2) == 1).then(|| rate(&board));
+            match new_rating.filter(|rating| rating.hardest.is_some()) {
+                Some(new_rating) => {
+                    rating = new_rating;
+                    clues -= 1;
+                }
+                None => board[row][col] = val,
+            }
+
+            if let Some(sender) = &context.progress {
+                // Receiver may be gone already, generation continues anyway
+                let _ = sender.send(Progress {
+                    attempt: context.attempt,
+                    tried_cells: tried as u16 + 1,
+                    clues,
+                    rating,
+                });
+            }
+        }
+
+        Some((rating, board))
+    }
+
+    // Splits solved board into cages of 2 to max_size connected cells with distinct values. A
+    // cell left without neighbors to join is merged into an adjacent cage if possible, otherwise
+    // it stays a cage of its own.
+    fn create_cages(solved_board: &Grid, max_size: u8, rng: &mut StdRng) -> Vec<Cage> {
+        let shape = solved_board.shape();
+        let size = shape.size as usize;
+        let value = |(row, col): (u8, u8)| solved_board[row as usize][col as usize];
+        let neighbors = |cell: (u8, u8)| neighbors(shape, cell);
+
+        let mut cages: Vec<Vec<(u8, u8)>> = Vec::new();
+        let mut cage_of: Grid<Option<usize>> = Grid::new(shape);
+        let mut all_indexes: Vec<usize> = (0..shape.cell_count()).collect();
+        all_indexes.shuffle(rng);
+        for index in all_indexes {
+            let start = ((index / size) as u8, (index % size) as u8);
+            if cage_of[start.0 as usize][start.1 as usize].is_some() {
+                continue;
+            }
+
+            let target_size = rng.random_range(2..=max_size.max(2)) as usize;
+            let mut cells = vec![start];
+            cage_of[start.0 as usize][start.1 as usize] = Some(cages.len());
+            while cells.len() < target_size {
+                let mut options: Vec<(u8, u8)> = cells
+                    .iter()
+                    .flat_map(|cell| neighbors(*cell))
+                    .filter(|cell| {
+                        cage_of[cell.0 as usize][cell.1 as usize].is_none()
+                            && cells.iter().all(|other| value(*other) != value(*cell))
+                    })
+                    .collect();
+                options.sort();
+                options.dedup();
+                if options.is_empty() {
+                    break;
+                }
+                let cell = options[rng.random_range(0..options.len())];
+                cage_of[cell.0 as usize][cell.1 as usize] = Some(cages.len());
+                cells.push(cell);
+            }
+            cages.push(cells);
+        }
+
+        // Merge single cells into an adjacent cage which has room and doesn't have their value
+        for index in 0..cages.len() {
+            if cages[index].len() != 1 {
+                continue;
+            }
+            let cell = cages[index][0];
+            let target = neighbors(cell)
+                .filter_map(|(row, col)| cage_of[row as usize][col as usize])
+                .find(|other| {
+                    cages[*other].len() > 1
+                        && cages[*other].len() < max_size as usize
+                        && cages[*other]
+                            .iter()
+                            .all(|other| value(*other) != value(cell))
+                });
+            if let Some(target) = target {
+                cages[index].clear();
+                cages[target].push(cell);
+                cage_of[cell.0 as usize][cell.1 as usize] = Some(target);
+            }
+        }
+
+        let mut cages: Vec<Cage> = cages
+            .into_iter()
+            .filter(|cells| !cells.is_empty())
+            .map(|mut cells| {
+                cells.sort();
+                Cage {
+                    sum: cells.iter().map(|cell| value(*cell) as u16).sum(),
+                    cells,
+                }
+            })
+            .collect();
+        cages.sort_by_key(|cage| cage.cells[0]);
+        cages
+    }
+
+    // Cells sharing a side with the cell
+    fn neighbors(shape: Shape, (row, col): (u8, u8)) -> impl Iterator<Item = (u8, u8)> {
+        let size = shape.size as i16;
+        let (row, col) = (row as i16, col as i16);
+        [
+            (row - 1, col),
+            (row + 1, col),
+            (row, col - 1),
+            (row, col + 1),
+        ]
+        .into_iter()
+        .filter(move |(row, col)| (0..size).contains(row) && (0..size).contains(col))
+        .map(|(row, col)| (row as u8, col as u8))
+    }
+
+    // Whether cells of the region given as in Grid::set_regions can all be reached from each
+    // other through neighbors in the region
+    fn is_connected(shape: Shape, regions: &[u8], region: u8) -> bool {
+        let size = shape.size as usize;
+        let cell = |index: usize| ((index / size) as u8, (index % size) as u8);
+        let index = |(row, col): (u8, u8)| row as usize * size + col as usize;
+        let cells: Vec<usize> = (0..regions.len())
+            .filter(|i| regions[*i] == region)
+            .collect();
+        let Some(&first) = cells.first() else {
+            return true;
+        };
+        let mut reached: Vec<usize> = vec![first];
+        let mut next = 0;
+        while next < reached.len() {
+            for neighbor in neighbors(shape, cell(reached[next])).map(index) {
+                if regions[neighbor] == region && !reached.contains(&neighbor) {
+                    reached.push(neighbor);
+                }
+            }
+            next += 1;
+        }
+        reached.len() == cells.len()
+    }
+
+    // Creates regions of a jigsaw board by starting from boxes and swapping random cells on the
+    // border of two regions, as long as both regions stay connected
+    fn create_regions(shape: Shape, rng: &mut StdRng) -> Vec<u8> {
+        let size = shape.size as usize;
+        let cell = |index: usize| ((index / size) as u8, (index % size) as u8);
+        let index = |(row, col): (u8, u8)| row as usize * size + col as usize;
+        let mut regions: Vec<u8> = (0..shape.cell_count())
+            .map(|i| shape.box_index(cell(i).0, cell(i).1))
+            .collect();
+
+        for _ in 0..4 * shape.cell_count() {
+            // Cell moving to a neighboring region, which gives back one of its cells bordering
+            // the region of the cell
+            let moving = rng.random_range(0..regions.len());
+            let from = regions[moving];
+            let targets: Vec<u8> = neighbors(shape, cell(moving))
+                .map(|neighbor| regions[index(neighbor)])
+                .filter(|region| *region != from)
+                .collect();
+            if targets.is_empty() {
+                continue;
+            }
+            let to = targets[rng.random_range(0..targets.len())];
+            let returning: Vec<usize> = (0..regions.len())
+                .filter(|i| {
+                    regions[*i] == to
+                        && neighbors(shape, cell(*i))
+                            .map(index)
+                            .any(|neighbor| neighbor != moving && regions[neighbor] == from)
+                })
+                .collect();
+            if returning.is_empty() {
+                continue;
+            }
+            let returning = returning[rng.random_range(0..returning.len())];
+
+            regions[moving] = to;
+            regions[returning] = from;
+            if !is_connected(shape, &regions, from) || !is_connected(shape, &regions, to) {
+                regions[moving] = from;
+                regions[returning] = to;
+            }
+        }
+
+        regions
+    }
+
+    // Work to be run by run_jobs
+    pub type Job<'a, T> = Box<dyn FnOnce() -> T + Send + 'a>;
+
+    // Runs jobs on at most thread_count threads and returns their results in order of jobs, so
+    // the result doesn't depend on the thread count. Jobs are dealt to workers evenly and a
+    // worker running out of jobs steals from the back of the others' queues, since some jobs
+    // take much longer than the others.
+    pub fn run_jobs<T: Send>(jobs: Vec<Job<T>>, thread_count: usize) -> Vec<T> {
+        let thread_count = thread_count.clamp(1, jobs.len().max(1));
+        let job_count = jobs.len();
+        let queues: Vec<Mutex<VecDeque<_>>> = (0..thread_count)
+            .map(|_| Mutex::new(VecDeque::new()))
+            .collect();
+        for (index, job) in jobs.into_iter().enumerate() {
+            queues[index % thread_count]
+                .lock()
+                .unwrap()
+                .push_back((index, job));
+        }
+
+        let results: Mutex<Vec<Option<T>>> = Mutex::new((0..job_count).map(|_| None).collect());
+        thread::scope(|scope| {
+            for worker in 0..thread_count {
+                let queues = &queues;
+                let results = &results;
+                scope.spawn(move || loop {
+                    // Own queue first, then other workers' queues
+                    let next = queues[worker].lock().unwrap().pop_front().or_else(|| {
+                        (1..thread_count).find_map(|offset| {
+                            queues[(worker + offset) % thread_count]
+                                .lock()
+                                .unwrap()
+                                .pop_back()
+                        })
+                    });
+                    // No job is added while running, so empty queues mean all jobs are taken
+                    let Some((index, job)) = next else {
+                        break;
+                    };
+                    let result = job();
+                    results.lock().unwrap()[index] = Some(result);
+                });
+            }
+        });
+
+        // Every job is run once the scope ends
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    // Cells tried for removal from the fallback board once the deadline is reached. Removing
+    // any cell from a solved board keeps it unique, so the first try always leaves an empty cell.
+    const LATE_REMOVAL_TRIES: usize = 8;
+
+    // Rounds of solved boards generated before giving up on the band, when time budget isn't
+    // exceeded first
+    const MAX_ATTEMPTS: u32 = 25;
+
+    // Random cells rarely have only a few solutions, taking the first ones is enough to choose from
+    const SOLVED_BOARDS_LIMIT: usize = 16;
+
+    // Search time of each try once the deadline is reached. Most random cells are solved or found
+    // to have no solution in a few milliseconds, some take seconds on large boards.
+    const LATE_TRY_TIME: Duration = Duration::from_millis(100);
+
+    // Fills some random cells of a board with the shape and constraints of options and returns
+    // first solutions of that board. Dancing links is used, since depth first search may get
+    // lost for long on large sparse boards. Search of a try stops at the deadline or once
+    // cancelled, after the deadline each try is given a short time only.
+    fn create_solved_boards(
+        options: &GenerationOptions,
+        rng: &mut StdRng,
+        cancel: &CancelToken,
+        deadline: Option<Instant>,
+    ) -> Vec<Grid> {
+        let shape = options.shape;
+        let mut solutions: Vec<Grid> = Vec::new();
+        let size = shape.size as usize;
+
+        while solutions.is_empty() && !cancel.is_cancelled() {
+            // Value 0 (zero) means cell is empty
+            let mut board = Grid::new(shape);
+            board.set_constraints(options.constraints.clone());
+            // Some regions have no solution at all, so each try gets new regions
+            if options.jigsaw {
+                board.set_regions(create_regions(shape, rng));
+            }
+
+            // Assign random but valid initial values
+            let mut all_indexes: Vec<usize> = (0..shape.cell_count()).collect();
+            all_indexes.shuffle(rng);
+            // 30 out of 81 is magic number, an optimized value. Random values rarely lead to a
+            // solution with constraints or regions, so 20 out of 81 are filled then, and 10 out
+            // of 81 with both.
+            let fill_count = match (options.constraints.is_empty(), options.jigsaw) {
+                (true, false) => shape.cell_count() * 30 / 81,
+                (false, true) => shape.cell_count() * 10 / 81,
+                _ => shape.cell_count() * 20 / 81,
+            };
+            for index in all_indexes.iter().take(fill_count) {
+                let row = index / size;
+                let col = index % size;
+                let available_values = available_values(&board, row as u8, col as u8);
+                if available_values.is_empty() {
+                    continue;
+                }
+                let index = rng.random::<u8>() % available_values.len() as u8;
+                board[row][col] = available_values[index as usize];
+            }
+
+            // Random values are valid where they are set, so the board needs no other checks
+            let try_deadline =
+                deadline.map(|deadline| deadline.max(Instant::now() + LATE_TRY_TIME));
+            solutions =
+                DlxSolver.solutions_until(&board, SOLVED_BOARDS_LIMIT, try_deadline, cancel);
+        }
+
+        solutions
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn mask(values: &[u8]) -> u32 {
+            values.iter().fold(0, |mask, val| mask | 1 << val)
+        }
+
+        // Classic board whose cells are all filled except the given ones, with their candidates
+        fn candidates_of(cells: &[((u8, u8), &[u8])]) -> Candidates {
+            let mut candidates: Candidates = Grid::new(Shape::CLASSIC);
+            for ((row, col), values) in cells {
+                candidates[*row as usize][*col as usize] = mask(values);
+            }
+            candidates
+        }
+
+        fn deduction(
+            technique: Technique,
+            cells: &[(u8, u8)],
+            placement: Option<(u8, u8, u8)>,
+            eliminations: &[(u8, u8, u8)],
+        ) -> Deduction {
+            Deduction {
+                technique,
+                cells: cells.to_vec(),
+                placement,
+                eliminations: eliminations.to_vec(),
+            }
+        }
+
+        // Options of a classic 9x9 board in band, generated on a single thread without time
+        // budget. Tests override the fields they are about.
+        fn generation_options(band: RangeInclusive<Technique>, seed: u64) -> GenerationOptions {
+            GenerationOptions {
+                shape: Shape::CLASSIC,
+                variant: Variant::Classic,
+                constraints: Vec::new(),
+                jigsaw: false,
+                band,
+                seed,
+                time_budget: None,
+                threads: 1,
+                symmetry: Symmetry::None,
+                minimal: false,
+            }
+        }
+
+        fn generate(options: &GenerationOptions) -> (Rating, Grid) {
+            generate_initial_board(options, &CancelToken::new(), None).unwrap()
+        }
+
+        #[test]
+        fn naked_single() {
+            let candidates = candidates_of(&[((4, 4), &[5]), ((4, 5), &[5, 6])]);
+            assert_eq!(
+                find_naked_single(&candidates),
+                Some(deduction(
+                    Technique::NakedSingle,
+                    &[(4, 4)],
+                    Some((4, 4, 5)),
+                    &[]
+                ))
+            );
+        }
+
+        #[test]
+        fn hidden_single() {
+            let candidates = candidates_of(&[((0, 0), &[1, 2]), ((0, 1), &[1, 2, 3])]);
+            assert_eq!(
+                find_hidden_single(&candidates),
+                Some(deduction(
+                    Technique::HiddenSingle,
+                    &[(0, 1)],
+                    Some((0, 1, 3)),
+                    &[]
+                ))
+            );
+        }
+
+        #[test]
+        fn pointing_pair() {
+            let candidates = candidates_of(&[((0, 0), &[7]), ((0, 1), &[7]), ((0, 5), &[6, 7])]);
+            assert_eq!(
+                find_pointing(&candidates),
+                Some(deduction(
+                    Technique::PointingPair,
+                    &[(0, 0), (0, 1)],
+                    None,
+                    &[(0, 5, 7)]
+                ))
+            );
+        }
+
+        #[test]
+        fn box_line_reduction() {
+            let candidates = candidates_of(&[((0, 0), &[4]), ((0, 2), &[4]), ((1, 1), &[4, 8])]);
+            assert_eq!(
+                find_box_line_reduction(&candidates),
+                Some(deduction(
+                    Technique::BoxLineReduction,
+                    &[(0, 0), (0, 2)],
+                    None,
+                    &[(1, 1, 4)]
+                ))
+            );
+        }
+
+        #[test]
+        fn naked_pair() {
+            let candidates =
+                candidates_of(&[((0, 0), &[1, 2]), ((0, 1), &[1, 2]), ((0, 2), &[1, 2, 3])]);
+            assert_eq!(
+                find_naked_subset(&candidates, 2),
+                Some(deduction(
+                    Technique::NakedPair,
+                    &[(0, 0), (0, 1)],
+                    None,
+                    &[(0, 2, 1), (0, 2, 2)]
+                ))
+            );
+        }
+
+        #[test]
+        fn hidden_pair() {
+            let candidates = candidates_of(&[
+                ((0, 0), &[1, 2, 5]),
+                ((0, 1), &[1, 2, 6]),
+                ((0, 2), &[5, 6]),
+                ((0, 3), &[5, 6]),
+            ]);
+            assert_eq!(
+                find_hidden_subset(&candidates, 2),
+                Some(deduction(
+                    Technique::HiddenPair,
+                    &[(0, 0), (0, 1)],
+                    None,
+                    &[(0, 0, 5), (0, 1, 6)]
+                ))
+            );
+        }
+
+        #[test]
+        fn naked_triple() {
+            let candidates = candidates_of(&[
+                ((0, 0), &[1, 2]),
+                ((0, 1), &[2, 3]),
+                ((0, 2), &[1, 3]),
+                ((0, 3), &[1, 2, 3, 4]),
+            ]);
+            assert_eq!(
+                find_naked_subset(&candidates, 3),
+                Some(deduction(
+                    Technique::NakedTriple,
+                    &[(0, 0), (0, 1), (0, 2)],
+                    None,
+                    &[(0, 3, 1), (0, 3, 2), (0, 3, 3)]
+                ))
+            );
+        }
+
+        #[test]
+        fn hidden_triple() {
+            let candidates = candidates_of(&[
+                ((0, 0), &[1, 2, 7]),
+                ((0, 1), &[2, 3, 8]),
+                ((0, 2), &[1, 3, 9]),
+                ((0, 3), &[7, 8, 9]),
+                ((0, 4), &[7, 8, 9]),
+            ]);
+            assert_eq!(
+                find_hidden_subset(&candidates, 3),
+                Some(deduction(
+                    Technique::HiddenTriple,
+                    &[(0, 0), (0, 1), (0, 2)],
+                    None,
+                    &[(0, 0, 7), (0, 1, 8), (0, 2, 9)]
+                ))
+            );
+        }
+
+        #[test]
+        fn x_wing() {
+            let candidates = candidates_of(&[
+                ((1, 2), &[5]),
+                ((1, 6), &[5]),
+                ((4, 2), &[5]),
+                ((4, 6), &[5]),
+                ((7, 0), &[5]),
+                ((7, 2), &[5]),
+            ]);
+            assert_eq!(
+                find_fish(&candidates, 2),
+                Some(deduction(
+                    Technique::XWing,
+                    &[(1, 2), (1, 6), (4, 2), (4, 6)],
+                    None,
+                    &[(7, 2, 5)]
+                ))
+            );
+        }
+
+        #[test]
+        fn swordfish() {
+            let candidates = candidates_of(&[
+                ((0, 1), &[5]),
+                ((0, 4), &[5]),
+                ((3, 4), &[5]),
+                ((3, 7), &[5]),
+                ((6, 1), &[5]),
+                ((6, 7), &[5]),
+                ((8, 1), &[5]),
+                ((8, 2), &[5]),
+                ((8, 3), &[5]),
+            ]);
+            assert_eq!(
+                find_fish(&candidates, 3),
+                Some(deduction(
+                    Technique::Swordfish,
+                    &[(0, 1), (0, 4), (3, 4), (3, 7), (6, 1), (6, 7)],
+                    None,
+                    &[(8, 1, 5)]
+                ))
+            );
+        }
+
+        #[test]
+        fn xy_wing() {
+            let candidates = candidates_of(&[
+                ((0, 0), &[1, 2]),
+                ((0, 4), &[1, 3]),
+                ((4, 0), &[2, 3]),
+                ((4, 4), &[3, 5, 6]),
+            ]);
+            assert_eq!(
+                find_xy_wing(&candidates),
+                Some(deduction(
+                    Technique::XYWing,
+                    &[(0, 0), (0, 4), (4, 0)],
+                    None,
+                    &[(4, 4, 3)]
+                ))
+            );
+        }
+
+        #[test]
+        fn innie_outie() {
+            let row: Vec<(u8, u8)> = (0..4).map(|col| (0, col)).collect();
+            // Cage of 3 is inside the first row, so the rest of the row adds up to 7
+            let board = parse_board("...4............|3:0,1;7:2,6").unwrap();
+            assert_eq!(
+                find_innie_outie(&board, &candidates(&board)),
+                Some(deduction(Technique::InnieOutie, &row, Some((0, 2, 3)), &[]))
+            );
+
+            // Cages covering the first row add up to 11, so the cell out of it is 1
+            let board = parse_board("................|3:0,1;8:2,3,7").unwrap();
+            assert_eq!(
+                find_innie_outie(&board, &candidates(&board)),
+                Some(deduction(Technique::InnieOutie, &row, Some((1, 3, 1)), &[]))
+            );
+
+            let board = parse_board("................|3:0,1;7:2,6").unwrap();
+            assert_eq!(find_innie_outie(&board, &candidates(&board)), None);
+        }
+
+        #[test]
+        fn simple_coloring() {
+            // Chain (0,0)-(0,4)-(4,4)-(4,1) alternates colors, (1,1) sees both ends
+            let candidates = candidates_of(&[
+                ((0, 0), &[1]),
+                ((0, 4), &[1]),
+                ((4, 4), &[1]),
+                ((4, 1), &[1]),
+                ((1, 1), &[1]),
+                ((2, 2), &[1]),
+                ((7, 1), &[1]),
+            ]);
+            assert_eq!(
+                find_simple_coloring(&candidates),
+                Some(deduction(
+                    Technique::SimpleColoring,
+                    &[(0, 0), (0, 4), (4, 4), (4, 1)],
+                    None,
+                    &[(1, 1, 1)]
+                ))
+            );
+        }
+
+        // Without time budget, minimal boards are still in band
+        #[test]
+        fn minimal_boards() {
+            for band in [
+                Technique::PointingPair..=Technique::HiddenTriple,
+                Technique::XWing..=Technique::Swordfish,
+            ] {
+                let options = GenerationOptions {
+                    minimal: true,
+                    ..generation_options(band.clone(), 2)
+                };
+                let (rating, board) = generate(&options);
+                assert!(rating.is_in(&band), "{:?}", band);
+                assert!(is_minimal(&board), "{:?}", band);
+            }
+
+            let options = GenerationOptions {
+                minimal: true,
+                ..generation_options(Technique::NakedSingle..=Technique::HiddenSingle, 1)
+            };
+            let (rating, mut board) = generate(&options);
+            assert!(rating.is_in(&options.band));
+            assert!(is_minimal(&board));
+
+            // Any value of the solution added back is redundant
+            let solution = find_solutions(&board, 1).remove(0);
+            let index = board.cells().iter().position(|val| *val == 0).unwrap();
+            let (row, col) = (index / 9, index % 9);
+            board[row][col] = solution[row][col];
+            assert!(!is_minimal(&board));
+        }
+
+        // Exceeding time budget at once keeps extra givens, board is still unique then
+        #[test]
+        fn killer_boards_are_unique() {
+            for (seed, time_budget) in [(1, None), (2, None), (3, Some(Duration::ZERO))] {
+                let options = GenerationOptions {
+                    variant: Variant::Killer { max_cage_size: 3 },
+                    time_budget,
+                    ..generation_options(Technique::NakedSingle..=Technique::HiddenSingle, seed)
+                };
+                let (rating, board) = generate(&options);
+                assert!(!board.cages().is_empty());
+                assert!(rating.hardest.is_some(), "seed {}", seed);
+                assert_eq!(DlxSolver.solutions(&board, 2).len(), 1, "seed {}", seed);
+            }
+        }
+
+        // A wrong elimination would make puzzles with several solutions look solved logically, so
+        // solvers are asked directly to catch that
+        #[test]
+        fn generated_boards_are_unique_and_solved_logically() {
+            let bands = [
+                Technique::NakedSingle..=Technique::HiddenSingle,
+                Technique::PointingPair..=Technique::HiddenTriple,
+                Technique::XWing..=Technique::Swordfish,
+            ];
+            for band in bands {
+                for seed in 1..=3 {
+                    let options = generation_options(band.clone(), seed);
+                    let (rating, board) = generate(&options);
+                    assert!(rating.is_in(&band), "seed {}", seed);
+                    assert_eq!(count_solutions(&board, 2), 1, "seed {}", seed);
+
+                    let (_, solved_board) = solve_logically(&board);
+                    assert!(is_finished(&solved_board), "seed {}", seed);
+                    assert_eq!(DfsSolver.solutions(&board, 2), vec![solved_board.clone()]);
+                    assert_eq!(DlxSolver.solutions(&board, 2), vec![solved_board]);
+                }
+            }
+        }
+
+        // Workers take jobs in whatever order they finish, the board must not depend on that
+        #[test]
+        fn generation_is_independent_of_threads() {
+            for seed in 1..=2 {
+                let options =
+                    generation_options(Technique::PointingPair..=Technique::HiddenTriple, seed);
+                let killer_options = GenerationOptions {
+                    variant: Variant::Killer { max_cage_size: 3 },
+                    ..options.clone()
+                };
+                for options in [options, killer_options] {
+                    let boards = [1, 4].map(|threads| {
+                        generate(&GenerationOptions {
+                            threads,
+                            ..options.clone()
+                        })
+                        .1
+                    });
+                    assert_eq!(boards[0], boards[1], "seed {}", seed);
+                }
+            }
+        }
+
+        // Empty 16x16 boards are searched with DLX, 9x9 ones with DFS
+        #[test]
+        fn count_solutions_of_boards() {
+            let empty = Grid::new(Shape::CLASSIC);
+            let unique = parse_board(PUZZLE).unwrap();
+            // First cell has no candidates left, though no values conflict
+            let unsolvable =
+                parse_board(&format!(".12345678{}9{}", ".".repeat(36), ".".repeat(35))).unwrap();
+            let conflicting = parse_board(&format!("55{}", ".".repeat(79))).unwrap();
+            assert_eq!(count_solutions(&empty, 2), 2);
+            assert_eq!(
+                count_solutions(&Grid::new(Shape::of_size(16).unwrap()), 2),
+                2
+            );
+            assert_eq!(count_solutions(&unique, 2), 1);
+            assert_eq!(count_solutions(&unsolvable, 2), 0);
+            assert_eq!(count_solutions(&conflicting, 2), 0);
+
+            let solvers: [&dyn Solver; 2] = [&DfsSolver, &DlxSolver];
+            for solver in solvers {
+                assert_eq!(solver.solutions(&empty, 2).len(), 2);
+                assert_eq!(solver.solutions(&unique, 2).len(), 1);
+                assert_eq!(solver.solutions(&unsolvable, 2).len(), 0);
+            }
+        }
+
+        // Orbits are built up from a rotation and a mirror instead of the table of orbit, so
+        // that a wrong entry in it shows up
+        fn dihedral_orbit(size: u8, cell: (u8, u8)) -> Vec<(u8, u8)> {
+            let last = size - 1;
+            let mut cells = vec![cell];
+            let mut i = 0;
+            while let Some(&(row, col)) = cells.get(i) {
+                for image in [(col, last - row), (col, row)] {
+                    if !cells.contains(&image) {
+                        cells.push(image);
+                    }
+                }
+                i += 1;
+            }
+            cells.sort();
+            cells
+        }
+
+        #[test]
+        fn symmetric_clue_patterns() {
+            let symmetries = [
+                Symmetry::Rotational,
+                Symmetry::MirrorHorizontal,
+                Symmetry::MirrorVertical,
+                Symmetry::Diagonal,
+                Symmetry::Dihedral,
+            ];
+            for shape in [Shape::CLASSIC, Shape::of_size(6).unwrap()] {
+                let size = shape.size;
+                for row in 0..size {
+                    for col in 0..size {
+                        assert_eq!(
+                            Symmetry::Dihedral.orbit(size, row, col),
+                            dihedral_orbit(size, (row, col))
+                        );
+                    }
+                }
+
+                for symmetry in symmetries {
+                    let options = GenerationOptions {
+                        shape,
+                        symmetry,
+                        ..generation_options(Technique::NakedSingle..=Technique::Swordfish, 1)
+                    };
+                    let (_, board) = generate(&options);
+                    assert_eq!(count_solutions(&board, 2), 1, "{:?}", symmetry);
+                    for row in 0..size {
+                        for col in 0..size {
+                            let orbit = symmetry.orbit(size, row, col);
+                            // Each cell of an orbit has the same orbit
+                            for &(row, col) in orbit.iter() {
+                                assert_eq!(symmetry.orbit(size, row, col), orbit);
+                            }
+                            let given =
+                                |&(row, col): &(u8, u8)| board[row as usize][col as usize] != 0;
+                            assert!(
+                                orbit.iter().all(given) || !orbit.iter().any(given),
+                                "{:?} {}x{} at row {}, col {}",
+                                symmetry,
+                                size,
+                                size,
+                                row,
+                                col
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        // Early jobs take longest, so workers finish them last and steal from each other
+        #[test]
+        fn job_results_are_in_order_of_jobs() {
+            let make_jobs = || -> Vec<Job<u64>> {
+                (0..20u64)
+                    .map(|i| -> Job<u64> {
+                        Box::new(move || {
+                            thread::sleep(Duration::from_millis(20 - i));
+                            i * i
+                        })
+                    })
+                    .collect()
+            };
+            let expected: Vec<u64> = (0..20).map(|i| i * i).collect();
+            for thread_count in [1, 2, 3, 8, 32] {
+                assert_eq!(run_jobs(make_jobs(), thread_count), expected);
+            }
+            assert!(run_jobs(Vec::<Job<u64>>::new(), 4).is_empty());
+        }
+
+        const PUZZLE: &str =
+            "53..7....6..195....98....6.8...6...34..8.3..17...2...6.6....28....419..5....8..79";
+
+        #[test]
+        fn parse_and_format_boards() {
+            let board = parse_board(PUZZLE).unwrap();
+            assert_eq!(board.shape(), Shape::CLASSIC);
+            assert_eq!((board[0][0], board[0][2], board[8][8]), (5, 0, 9));
+            assert_eq!(format_board(&board), PUZZLE);
+            assert_eq!(format_puzzle(&board), PUZZLE);
+
+            // Zeros for empty cells and a grid of lines are read the same
+            let lines: Vec<String> = PUZZLE
+                .replace('.', "0")
+                .as_bytes()
+                .chunks(9)
+                .map(|line| format!("  {}\n", String::from_utf8_lossy(line)))
+                .collect();
+            assert_eq!(parse_board(&lines.concat()).unwrap(), board);
+
+            // Letters are values above 9 in either case
+            let lower = parse_board(&format!("a{}", ".".repeat(143))).unwrap();
+            let upper = parse_board(&format!("A{}", ".".repeat(143))).unwrap();
+            assert_eq!(lower, upper);
+            assert_eq!(format_board(&lower).chars().next(), Some('A'));
+        }
+
+        const SOLUTION: &str =
+            "534678912672195348198342567859761423426853791713924856961537284287419635345286179";
+
+        #[test]
+        fn available_values_of_cells() {
+            let board = parse_board(PUZZLE).unwrap();
+            assert_eq!(available_values(&board, 0, 2), vec![1, 2, 4]);
+            assert_eq!(available_values(&board, 0, 0), vec![5]);
+
+            // Cells of a diagonal and of a cage have distinct values
+            let board = parse_board("1...............").unwrap();
+            assert_eq!(available_values(&board, 3, 3), vec![1, 2, 3, 4]);
+            let board = parse_board("1...............|X").unwrap();
+            assert_eq!(available_values(&board, 3, 3), vec![2, 3, 4]);
+            let board = parse_board(".....2..........|5:5,10").unwrap();
+            assert_eq!(available_values(&board, 2, 2), vec![1, 3, 4]);
+        }
+
+        #[test]
+        fn valid_cells() {
+            let mut board = parse_board(PUZZLE).unwrap();
+            assert!((0..9).all(|row| (0..9).all(|col| is_valid(&board, row, col))));
+            board[0][2] = 5;
+            assert!(!is_valid(&board, 0, 2));
+            assert!(!is_valid(&board, 0, 0));
+            assert!(is_valid(&board, 0, 3));
+
+            let board = parse_board("1..............1").unwrap();
+            assert!(is_valid(&board, 3, 3));
+            let board = parse_board("1..............1|X").unwrap();
+            assert!(!is_valid(&board, 3, 3));
+
+            // Sum of a cage can't be exceeded, nor missed once it is full
+            let mut board = parse_board("1...............|3:0,1").unwrap();
+            assert!(is_valid(&board, 0, 0));
+            board[0][1] = 3;
+            assert!(!is_valid(&board, 0, 1));
+            board[0][1] = 2;
+            assert!(is_valid(&board, 0, 1));
+            let board = parse_board("1...............|4:0,1").unwrap();
+            assert!(is_valid(&board, 0, 0));
+            let mut board = parse_board("12..............|4:0,1").unwrap();
+            assert!(!is_valid(&board, 0, 0));
+            board[0][1] = 0;
+            assert!(is_valid(&board, 0, 0));
+        }
+
+        #[test]
+        fn finished_boards() {
+            let mut board = parse_board(SOLUTION).unwrap();
+            assert!(is_finished(&board));
+            assert!(!is_finished(&parse_board(PUZZLE).unwrap()));
+
+            // Full but with conflicts
+            (board[0][0], board[0][1]) = (board[0][1], board[0][0]);
+            assert!(!is_finished(&board));
+        }
+
+        #[test]
+        fn malformed_boards() {
+            assert_eq!(
+                parse_board(&PUZZLE[1..]),
+                Err(String::from(
+                    "expected 16, 36, 81, 144 or 256 cells, found 80"
+                ))
+            );
+            assert_eq!(
+                parse_board(&PUZZLE.replacen('.', "x", 1)),
+                Err(String::from("invalid character 'x' at row 1, col 3"))
+            );
+            // Values above the size are invalid characters too
+            assert_eq!(
+                parse_board("5..............."),
+                Err(String::from("invalid character '5' at row 1, col 1"))
+            );
+        }
+
+        // Constraint names are matched in any case and surrounding whitespace is ignored
+        #[test]
+        fn parse_and_format_constraints() {
+            let board = parse_board(&format!("{}|X", PUZZLE)).unwrap();
+            assert_eq!(board.constraints(), &[Constraint::Diagonals]);
+            assert_eq!(format_puzzle(&board), format!("{}|X", PUZZLE));
+            assert_eq!(parse_board(&format!("{}| x ", PUZZLE)).unwrap(), board);
+
+            assert_eq!(
+                parse_board(&format!("{}|X|x", PUZZLE)),
+                Err(String::from("constraint x is given twice"))
+            );
+        }
+
+        #[test]
+        fn parse_and_format_regions() {
+            let text = "1...............|AAABACBBCCDBCDDD";
+            let board = parse_board(text).unwrap();
+            assert_eq!(
+                board.regions(),
+                Some(&[0, 0, 0, 1, 0, 2, 1, 1, 2, 2, 3, 1, 2, 3, 3, 3][..])
+            );
+            assert_eq!(format_puzzle(&board), text);
+            assert_eq!(parse_board(&text.to_lowercase()).unwrap(), board);
+            // Regions and constraints may come in any order
+            let diagonals = parse_board("1...............|X|AAABACBBCCDBCDDD").unwrap();
+            assert_eq!(diagonals.regions(), board.regions());
+            assert_eq!(
+                format_puzzle(&diagonals),
+                "1...............|X|AAABACBBCCDBCDDD"
+            );
+
+            assert_eq!(
+                parse_board("................|AAAAACBBCCDBCDDD"),
+                Err(String::from("region A has 5 cells, expected 4"))
+            );
+            assert_eq!(
+                parse_board("................|AAABACBBCCDBCDDE"),
+                Err(String::from("region D has 3 cells, expected 4"))
+            );
+            assert_eq!(
+                parse_board("................|AAABACBBCCDBCDD"),
+                Err(String::from(
+                    "expected a region letter for each of 16 cells, found 15"
+                ))
+            );
+            assert_eq!(
+                parse_board("................|AAABACBBCCDBCDDD|AAABACBBCCDBCDDD"),
+                Err(String::from("regions are given twice"))
+            );
+            assert_eq!(
+                parse_board("................|AABBAABBCCDDDDCC"),
+                Err(String::from("region C is not connected"))
+            );
+            assert_eq!(
+                parse_board("................|"),
+                Err(String::from("empty section after '|'"))
+            );
+            assert_eq!(
+                parse_board("................| |AAABACBBCCDBCDDD"),
+                Err(String::from("empty section after '|'"))
+            );
+        }
+
+        #[test]
+        fn parse_and_format_cages() {
+            let text = "................|3:0,1;7:6,2,3";
+            let board = parse_board(text).unwrap();
+            assert_eq!(
+                board.cages(),
+                &[
+                    Cage {
+                        cells: vec![(0, 0), (0, 1)],
+                        sum: 3
+                    },
+                    Cage {
+                        cells: vec![(0, 2), (0, 3), (1, 2)],
+                        sum: 7
+                    },
+                ]
+            );
+            // Cells are formatted sorted
+            let formatted = "................|3:0,1;7:2,3,6";
+            assert_eq!(format_puzzle(&board), formatted);
+            assert_eq!(parse_board(formatted).unwrap(), board);
+            assert_eq!(
+                parse_board("................| 3: 0, 1 ;7:2,3,6;").unwrap(),
+                board
+            );
+
+            assert_eq!(
+                parse_board("................|3:0,1;7:1,2"),
+                Err(String::from("cell 1 is in more than one cage"))
+            );
+            assert_eq!(
+                parse_board("................|3:0,16"),
+                Err(String::from("cell 16 of cage '3:0,16' is out of board"))
+            );
+            assert_eq!(
+                parse_board("................|3:0,1,"),
+                Err(String::from("invalid cage '3:0,1,'"))
+            );
+            assert_eq!(
+                parse_board("................|3-0,1"),
+                Err(String::from("invalid cage '3-0,1'"))
+            );
+            assert_eq!(
+                parse_board("................|3:0,1|7:2,3"),
+                Err(String::from("cages are given twice"))
+            );
+        }
+
+        // 16x16 boards have hex digits as in hexadoku, where '0' is a value and not an empty cell
+        #[test]
+        fn hex_values_of_16x16_boards() {
+            let shape = Shape::of_size(16).unwrap();
+            let chars: String = (1..=16).map(|val| shape.value_char(val)).collect();
+            assert_eq!(chars, "0123456789ABCDEF");
+            assert_eq!(shape.char_value('0'), Some(1));
+            assert_eq!(shape.char_value('F'), Some(16));
+            assert_eq!(shape.char_value('G'), None);
+            assert_eq!(Shape::CLASSIC.char_value('0'), None);
+            assert_eq!(Shape::of_size(12).unwrap().char_value('C'), Some(12));
+
+            let text = format!("0.f{}", ".".repeat(253));
+            let board = parse_board(&text).unwrap();
+            assert_eq!(&board.cells()[..3], &[1, 0, 16]);
+            assert_eq!(format_board(&board), text.to_uppercase());
+            assert!(parse_board(&format!("G{}", ".".repeat(255))).is_err());
+        }
+
+        // Boards tried after the deadline are still puzzles, not solved boards left as they are
+        #[test]
+        fn boards_exceeding_time_budget_have_empty_cells() {
+            // Large boards are slow to search, so one seed is enough for them
+            let runs = [
+                (Shape::CLASSIC, 1..=3),
+                (Shape::of_size(12).unwrap(), 1..=1),
+            ];
+            for (shape, seeds) in runs {
+                for seed in seeds {
+                    let options = GenerationOptions {
+                        shape,
+                        time_budget: Some(Duration::ZERO),
+                        minimal: seed == 1,
+                        ..generation_options(Technique::XWing..=Technique::Swordfish, seed)
+                    };
+                    let (rating, board) = generate(&options);
+                    assert!(board.cells().contains(&0), "seed {}", seed);
+                    assert!(rating.hardest.is_some(), "seed {}", seed);
+                    assert_eq!(count_solutions(&board, 2), 1, "seed {}", seed);
+                }
+            }
+        }
+
+        // Solutions of an empty board are countless, search would never end if not cancelled
+        #[test]
+        fn cancelled_generation_stops() {
+            let cancel = CancelToken::new();
+            cancel.cancel();
+            let board = Grid::new(Shape::of_size(16).unwrap());
+            assert!(
+                DlxSolver
+                    .solutions_until(&board, usize::MAX, None, &cancel)
+                    .len()
+                    <= 1
+            );
+
+            // Cancelled while searching solved boards or removing cells
+            let cancel = CancelToken::new();
+            let options = GenerationOptions {
+                shape: Shape::of_size(16).unwrap(),
+                ..generation_options(Technique::XWing..=Technique::Swordfish, 1)
+            };
+            thread::scope(|scope| {
+                let generation = scope.spawn(|| generate_initial_board(&options, &cancel, None));
+                thread::sleep(Duration::from_millis(100));
+                cancel.cancel();
+                assert!(generation.join().unwrap().is_none());
+            });
+        }
+
+        // Singles are enough for any 4x4 puzzle, so the band is never reached
+        #[test]
+        fn unreachable_bands_fall_back_without_time_budget() {
+            let options = GenerationOptions {
+                shape: Shape::of_size(4).unwrap(),
+                ..generation_options(Technique::XWing..=Technique::Swordfish, 1)
+            };
+            let (rating, board) = generate(&options);
+            assert!(!rating.is_in(&options.band));
+            assert!(board.cells().contains(&0));
+            assert_eq!(count_solutions(&board, 2), 1);
+        }
     }
 }